@@ -4,13 +4,12 @@ use anyhow::Result;
 use fs_extra::{copy_items, dir::CopyOptions};
 
 fn main() -> Result<()> {
-    println!("cargo:rerun-if-changed=resources/*");
+    println!("cargo:rerun-if-changed=resources");
 
     let out_dir = env::var("OUT_DIR")?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("resources/");
+    let paths_to_copy = vec!["resources/"];
     copy_items(&paths_to_copy, out_dir, &copy_options)?;
 
     Ok(())
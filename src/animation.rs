@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::body::Body;
+
+/// What happens when an animation reaches its last frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackMode {
+    /// Start over from the first frame.
+    Loop,
+    /// Play backwards to the first frame, then forwards again.
+    PingPong,
+    /// Stay on the last frame.
+    Once,
+}
+
+pub struct AnimationFrame {
+    /// The name of the frame's image in the sprite atlas.
+    pub region: String,
+    /// How long the frame is shown, in seconds.
+    pub duration: f32,
+    /// Fired whenever the frame starts showing, e.g. to play a footstep.
+    pub event: Option<String>,
+}
+
+impl AnimationFrame {
+    pub fn new(region: &str, duration: f32) -> Self {
+        Self {
+            region: region.into(),
+            duration,
+            event: None,
+        }
+    }
+
+    pub fn with_event(mut self, event: &str) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+}
+
+pub struct AnimationClip {
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlaybackMode,
+}
+
+impl AnimationClip {
+    pub fn new(frames: Vec<AnimationFrame>, mode: PlaybackMode) -> Self {
+        assert!(
+            !frames.is_empty(),
+            "animation clips need at least one frame"
+        );
+        Self { frames, mode }
+    }
+}
+
+/// The states of a character's animation state machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnimationState {
+    Idle,
+    Run,
+    Jump,
+    Fall,
+}
+
+impl AnimationState {
+    /// Picks the state matching how `body` is moving.
+    pub fn from_body(body: &Body) -> Self {
+        // Below this horizontal speed a grounded body counts as standing still
+        const RUN_THRESHOLD: f32 = 1.;
+
+        if body.grounded {
+            if body.velocity.x.abs() > RUN_THRESHOLD {
+                AnimationState::Run
+            } else {
+                AnimationState::Idle
+            }
+        } else if body.velocity.y < 0. {
+            AnimationState::Jump
+        } else {
+            AnimationState::Fall
+        }
+    }
+}
+
+/// Plays the clip belonging to the current `AnimationState`, switching clips
+/// as the body it follows starts and stops moving.
+pub struct Animator {
+    pub clips: HashMap<AnimationState, AnimationClip>,
+    pub state: AnimationState,
+    /// Whether the sprite should be mirrored to face left.
+    pub flip_x: bool,
+    frame: usize,
+    time: f32,
+    reversed: bool,
+}
+
+impl Animator {
+    pub fn new(clips: HashMap<AnimationState, AnimationClip>, state: AnimationState) -> Self {
+        assert!(
+            clips.contains_key(&state),
+            "no animation clip for the initial state {:?}",
+            state
+        );
+
+        Self {
+            clips,
+            state,
+            flip_x: false,
+            frame: 0,
+            time: 0.,
+            reversed: false,
+        }
+    }
+
+    /// Switches to `state`, restarting its clip if it isn't already playing.
+    /// States without a clip are ignored.
+    pub fn set_state(&mut self, state: AnimationState, events: &mut Vec<String>) {
+        if state == self.state || !self.clips.contains_key(&state) {
+            return;
+        }
+
+        self.state = state;
+        self.frame = 0;
+        self.time = 0.;
+        self.reversed = false;
+        events.extend(self.current_frame().event.clone());
+    }
+
+    /// Runs the state machine for `body` and advances the animation by `dt`
+    /// seconds, pushing the events of every frame that started into `events`.
+    pub fn update(&mut self, body: &Body, dt: f32, events: &mut Vec<String>) {
+        if body.velocity.x != 0. {
+            self.flip_x = body.velocity.x < 0.;
+        }
+
+        self.set_state(AnimationState::from_body(body), events);
+        self.advance(dt, events);
+    }
+
+    pub fn advance(&mut self, dt: f32, events: &mut Vec<String>) {
+        self.time += dt;
+
+        loop {
+            let clip = &self.clips[&self.state];
+            let duration = clip.frames[self.frame].duration;
+            if self.time < duration || duration <= 0. {
+                break;
+            }
+
+            let Some(next) = next_frame(clip, self.frame, &mut self.reversed) else {
+                // Once clips hold their last frame
+                self.time = 0.;
+                break;
+            };
+
+            self.time -= duration;
+            self.frame = next;
+            events.extend(clip.frames[next].event.clone());
+        }
+    }
+
+    pub fn current_frame(&self) -> &AnimationFrame {
+        &self.clips[&self.state].frames[self.frame]
+    }
+}
+
+fn next_frame(clip: &AnimationClip, frame: usize, reversed: &mut bool) -> Option<usize> {
+    let last = clip.frames.len() - 1;
+
+    match clip.mode {
+        PlaybackMode::Loop => Some(if frame == last { 0 } else { frame + 1 }),
+        PlaybackMode::Once => (frame < last).then_some(frame + 1),
+        PlaybackMode::PingPong if last == 0 => Some(0),
+        PlaybackMode::PingPong => {
+            if (*reversed && frame == 0) || (!*reversed && frame == last) {
+                *reversed = !*reversed;
+            }
+
+            Some(if *reversed { frame - 1 } else { frame + 1 })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::collision::Aabb;
+
+    const FRAME: f32 = 0.25;
+
+    /// A clip of `count` frames named after their index, each firing an
+    /// event with the same name.
+    fn clip(count: usize, mode: PlaybackMode) -> AnimationClip {
+        let frames = (0..count)
+            .map(|i| AnimationFrame::new(&i.to_string(), FRAME).with_event(&i.to_string()))
+            .collect();
+        AnimationClip::new(frames, mode)
+    }
+
+    fn animator(clip: AnimationClip) -> Animator {
+        Animator::new(
+            HashMap::from([(AnimationState::Idle, clip)]),
+            AnimationState::Idle,
+        )
+    }
+
+    /// The frame shown after each of `steps` frame-long advances.
+    fn play(animator: &mut Animator, steps: usize) -> Vec<String> {
+        (0..steps)
+            .map(|_| {
+                animator.advance(FRAME, &mut Vec::new());
+                animator.current_frame().region.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_to_the_first_frame() {
+        let mut animator = animator(clip(3, PlaybackMode::Loop));
+        assert_eq!(play(&mut animator, 5), ["1", "2", "0", "1", "2"]);
+    }
+
+    #[test]
+    fn ping_pong_bounces_at_both_ends() {
+        let mut animator = animator(clip(3, PlaybackMode::PingPong));
+        assert_eq!(play(&mut animator, 7), ["1", "2", "1", "0", "1", "2", "1"]);
+    }
+
+    #[test]
+    fn ping_pong_with_one_frame_stays_on_it() {
+        let mut animator = animator(clip(1, PlaybackMode::PingPong));
+        assert_eq!(play(&mut animator, 3), ["0", "0", "0"]);
+    }
+
+    #[test]
+    fn once_holds_the_last_frame() {
+        let mut animator = animator(clip(3, PlaybackMode::Once));
+        let mut events = Vec::new();
+        for _ in 0..5 {
+            animator.advance(FRAME, &mut events);
+        }
+
+        assert_eq!(animator.current_frame().region, "2");
+        assert_eq!(events, ["1", "2"]);
+    }
+
+    #[test]
+    fn frame_events_fire_once_per_entry() {
+        let mut animator = animator(clip(2, PlaybackMode::Loop));
+        let mut events = Vec::new();
+
+        // Several small steps inside one frame only fire its event once
+        for _ in 0..10 {
+            animator.advance(FRAME / 10., &mut events);
+        }
+        animator.advance(FRAME / 20., &mut events);
+        assert_eq!(events, ["1"]);
+
+        // and a step spanning several frames fires each of them
+        animator.advance(FRAME * 3., &mut events);
+        assert_eq!(events, ["1", "0", "1", "0"]);
+    }
+
+    #[test]
+    fn animator_picks_states_from_the_body() {
+        let clips = [
+            AnimationState::Idle,
+            AnimationState::Run,
+            AnimationState::Jump,
+            AnimationState::Fall,
+        ]
+        .into_iter()
+        .map(|state| {
+            let frame = AnimationFrame::new(&format!("{:?}", state), FRAME)
+                .with_event(&format!("{:?}", state));
+            (state, AnimationClip::new(vec![frame], PlaybackMode::Loop))
+        })
+        .collect();
+        let mut animator = Animator::new(clips, AnimationState::Idle);
+        let mut body = Body::new(Aabb::from_position_size(Vec2::ZERO, Vec2::splat(8.)), 300.);
+        let mut update = |grounded, velocity| {
+            body.grounded = grounded;
+            body.velocity = velocity;
+            let mut events = Vec::new();
+            animator.update(&body, 0., &mut events);
+            (animator.state, animator.flip_x, events)
+        };
+
+        assert_eq!(
+            update(true, Vec2::ZERO),
+            (AnimationState::Idle, false, vec![])
+        );
+        assert_eq!(
+            update(true, Vec2::new(-50., 0.)),
+            (AnimationState::Run, true, vec!["Run".into()])
+        );
+        // Staying in a state doesn't restart its clip
+        assert_eq!(
+            update(true, Vec2::new(-60., 0.)),
+            (AnimationState::Run, true, vec![])
+        );
+        assert_eq!(
+            update(false, Vec2::new(0., -100.)),
+            (AnimationState::Jump, true, vec!["Jump".into()])
+        );
+        assert_eq!(
+            update(false, Vec2::new(20., 100.)),
+            (AnimationState::Fall, false, vec!["Fall".into()])
+        );
+    }
+
+    #[test]
+    fn states_without_a_clip_are_ignored() {
+        let mut animator = animator(clip(2, PlaybackMode::Loop));
+        let mut events = Vec::new();
+        animator.set_state(AnimationState::Run, &mut events);
+
+        assert_eq!(animator.state, AnimationState::Idle);
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use glam::{UVec2, Vec4};
+use image::{DynamicImage, RgbaImage};
+use wgpu::{Device, Queue};
+
+use crate::{resources, texture::Texture};
+
+/// The largest atlas we'll build, which every wgpu backend supports.
+const MAX_ATLAS_SIZE: u32 = 8192;
+
+/// Where one of the packed images ended up in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+    /// In the format of `Instance::uv_rect`.
+    pub uv_rect: Vec4,
+    /// The size of the image in pixels.
+    pub size: UVec2,
+}
+
+impl AtlasRegion {
+    /// Maps `uv_rect`, given relative to the packed image, into the atlas.
+    /// Used to pick a tile out of a tileset that was packed as a whole.
+    pub fn sub_rect(&self, uv_rect: Vec4) -> Vec4 {
+        Vec4::new(
+            self.uv_rect.x + uv_rect.x * self.uv_rect.z,
+            self.uv_rect.y + uv_rect.y * self.uv_rect.w,
+            uv_rect.z * self.uv_rect.z,
+            uv_rect.w * self.uv_rect.w,
+        )
+    }
+}
+
+/// Many images packed into one texture, so sprites using any of them can be
+/// drawn in the same batch.
+pub struct TextureAtlas {
+    pub texture: Texture,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+/// Collects images and packs them into a `TextureAtlas`.
+///
+/// Every image is surrounded by `padding` pixels that repeat its outermost
+/// row or column. When a subpixel camera offset or float rounding lands a
+/// sample just past the edge of a sprite, it picks up the sprite's own edge
+/// rather than bleeding in whatever was packed next to it.
+pub struct AtlasBuilder {
+    pub padding: u32,
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(padding: u32) -> Self {
+        Self {
+            padding,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn add_image(&mut self, name: &str, image: &DynamicImage) {
+        self.images.push((name.into(), image.to_rgba8()));
+    }
+
+    /// Loads an image from the resources directory, named after its file.
+    pub async fn load(&mut self, file_name: &str) -> Result<()> {
+        let bytes = resources::load_binary(file_name)
+            .await
+            .with_context(|| format!("failed to load {}", file_name))?;
+        let image = image::load_from_memory(&bytes)
+            .with_context(|| format!("failed to decode {}", file_name))?;
+
+        self.add_image(file_name, &image);
+        Ok(())
+    }
+
+    /// Packs the images into rows, tallest first, and returns the atlas image
+    /// along with where each image was placed.
+    pub fn pack(self) -> Result<(RgbaImage, HashMap<String, AtlasRegion>)> {
+        let padding = self.padding;
+        let padded = |image: &RgbaImage| UVec2::new(image.width(), image.height()) + 2 * padding;
+
+        let area: u32 = self
+            .images
+            .iter()
+            .map(|(_, image)| padded(image).x * padded(image).y)
+            .sum();
+        let widest = self
+            .images
+            .iter()
+            .map(|(_, image)| padded(image).x)
+            .max()
+            .unwrap_or(1);
+        let width = ((area as f32).sqrt().ceil() as u32)
+            .max(widest)
+            .next_power_of_two();
+
+        if width > MAX_ATLAS_SIZE {
+            bail!(
+                "atlas would be {} pixels wide, the limit is {}",
+                width,
+                MAX_ATLAS_SIZE
+            );
+        }
+
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|&index| std::cmp::Reverse(self.images[index].1.height()));
+
+        // Shelf packing: fill a row left to right, then start a new row below
+        // the tallest image in it
+        let mut positions = vec![UVec2::ZERO; self.images.len()];
+        let mut cursor = UVec2::ZERO;
+        let mut row_height = 0;
+        for &index in &order {
+            let size = padded(&self.images[index].1);
+            if cursor.x + size.x > width {
+                cursor = UVec2::new(0, cursor.y + row_height);
+                row_height = 0;
+            }
+
+            positions[index] = cursor;
+            cursor.x += size.x;
+            row_height = row_height.max(size.y);
+        }
+
+        let height = (cursor.y + row_height).max(1).next_power_of_two();
+        if height > MAX_ATLAS_SIZE {
+            bail!(
+                "atlas would be {} pixels tall, the limit is {}",
+                height,
+                MAX_ATLAS_SIZE
+            );
+        }
+
+        let mut atlas = RgbaImage::new(width, height);
+        let mut regions = HashMap::new();
+        let atlas_size = UVec2::new(width, height).as_vec2();
+
+        for ((name, image), position) in self.images.into_iter().zip(positions) {
+            let origin = position + padding;
+            blit_extruded(&mut atlas, &image, origin, padding);
+
+            let size = UVec2::new(image.width(), image.height());
+            let min = origin.as_vec2() / atlas_size;
+            let extent = size.as_vec2() / atlas_size;
+
+            regions.insert(
+                name,
+                AtlasRegion {
+                    uv_rect: Vec4::new(min.x, min.y, extent.x, extent.y),
+                    size,
+                },
+            );
+        }
+
+        Ok((atlas, regions))
+    }
+
+    /// Packs the images and uploads the result to the GPU.
+    pub fn build(self, device: &Device, queue: &Queue, label: &str) -> Result<TextureAtlas> {
+        let (image, regions) = self.pack()?;
+        let texture =
+            Texture::from_image(device, queue, &DynamicImage::ImageRgba8(image), Some(label))?;
+
+        Ok(TextureAtlas { texture, regions })
+    }
+}
+
+/// Copies `image` into `atlas` at `origin` and smears its border pixels out
+/// over the surrounding `padding`.
+fn blit_extruded(atlas: &mut RgbaImage, image: &RgbaImage, origin: UVec2, padding: u32) {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let padding = padding as i64;
+    for y in -padding..height as i64 + padding {
+        for x in -padding..width as i64 + padding {
+            let source_x = x.clamp(0, width as i64 - 1) as u32;
+            let source_y = y.clamp(0, height as i64 - 1) as u32;
+            let pixel = image.get_pixel(source_x, source_y);
+
+            atlas.put_pixel(
+                (origin.x as i64 + x) as u32,
+                (origin.y as i64 + y) as u32,
+                *pixel,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// An image where every pixel is different, so tests can tell which one
+    /// ended up where.
+    fn gradient(width: u32, height: u32, seed: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([x as u8, y as u8, seed, 255])
+        }))
+    }
+
+    /// The pixel rect a region covers in the atlas, as (min, max).
+    fn pixel_rect(region: &AtlasRegion, atlas: &RgbaImage) -> (UVec2, UVec2) {
+        let atlas_size = UVec2::new(atlas.width(), atlas.height()).as_vec2();
+        let min = (region.uv_rect.truncate().truncate() * atlas_size)
+            .round()
+            .as_uvec2();
+        (min, min + region.size)
+    }
+
+    #[test]
+    fn packed_images_dont_overlap() {
+        let padding = 2;
+        let mut builder = AtlasBuilder::new(padding);
+        for (i, (width, height)) in [(16, 16), (32, 8), (8, 24), (5, 5), (40, 3), (16, 16)]
+            .into_iter()
+            .enumerate()
+        {
+            builder.add_image(&i.to_string(), &gradient(width, height, i as u8));
+        }
+
+        let (atlas, regions) = builder.pack().unwrap();
+        assert!(atlas.width().is_power_of_two() && atlas.height().is_power_of_two());
+
+        let padded: Vec<_> = regions
+            .values()
+            .map(|region| {
+                let (min, max) = pixel_rect(region, &atlas);
+                (min - padding, max + padding)
+            })
+            .collect();
+        for (i, &(min, max)) in padded.iter().enumerate() {
+            assert!(max.x <= atlas.width() && max.y <= atlas.height());
+            for &(other_min, other_max) in &padded[i + 1..] {
+                let overlaps = min.x < other_max.x
+                    && other_min.x < max.x
+                    && min.y < other_max.y
+                    && other_min.y < max.y;
+                assert!(
+                    !overlaps,
+                    "{:?} overlaps {:?}",
+                    (min, max),
+                    (other_min, other_max)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uvs_cover_exactly_the_image_inside_the_padding() {
+        let mut builder = AtlasBuilder::new(3);
+        builder.add_image("sprite", &gradient(4, 6, 7));
+
+        let (atlas, regions) = builder.pack().unwrap();
+        let region = regions["sprite"];
+        assert_eq!(region.size, UVec2::new(4, 6));
+
+        let (min, max) = pixel_rect(&region, &atlas);
+        assert_eq!(min, UVec2::splat(3));
+        assert_eq!(max - min, region.size);
+        assert_eq!(*atlas.get_pixel(min.x, min.y), Rgba([0, 0, 7, 255]));
+        assert_eq!(*atlas.get_pixel(max.x - 1, max.y - 1), Rgba([3, 5, 7, 255]));
+    }
+
+    #[test]
+    fn padding_repeats_the_edge_texels() {
+        let padding = 2;
+        let mut builder = AtlasBuilder::new(padding);
+        builder.add_image("sprite", &gradient(3, 3, 1));
+
+        let (atlas, regions) = builder.pack().unwrap();
+        let (min, max) = pixel_rect(&regions["sprite"], &atlas);
+        let texel = |x, y| Rgba([x, y, 1, 255]);
+
+        for offset in 1..=padding {
+            // Edges repeat the row or column next to them
+            assert_eq!(*atlas.get_pixel(min.x - offset, min.y + 1), texel(0, 1));
+            assert_eq!(*atlas.get_pixel(max.x - 1 + offset, min.y + 1), texel(2, 1));
+            assert_eq!(*atlas.get_pixel(min.x + 1, min.y - offset), texel(1, 0));
+            assert_eq!(*atlas.get_pixel(min.x + 1, max.y - 1 + offset), texel(1, 2));
+            // and corners repeat the corner texel
+            assert_eq!(
+                *atlas.get_pixel(min.x - offset, min.y - offset),
+                texel(0, 0)
+            );
+            assert_eq!(
+                *atlas.get_pixel(max.x - 1 + offset, max.y - 1 + offset),
+                texel(2, 2)
+            );
+        }
+    }
+
+    #[test]
+    fn overflowing_the_atlas_is_an_error() {
+        let mut builder = AtlasBuilder::new(1);
+        builder.add_image("wide", &gradient(MAX_ATLAS_SIZE, 1, 0));
+
+        let error = builder.pack().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "atlas would be 16384 pixels wide, the limit is 8192"
+        );
+    }
+}
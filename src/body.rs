@@ -0,0 +1,183 @@
+use glam::Vec2;
+
+use crate::collision::{Aabb, Contacts};
+
+/// A kinematic body that moves an `Instance` around. Velocities are in world
+/// units per second, and since +y points down on screen, gravity is positive.
+#[derive(Clone, Copy)]
+pub struct Body {
+    pub velocity: Vec2,
+    pub acceleration: Vec2,
+    pub gravity_scale: f32,
+    pub max_fall_speed: f32,
+    pub grounded: bool,
+    /// Whether what the body is standing on is one-way, so it could drop
+    /// through.
+    pub on_one_way: bool,
+    /// How many more ticks one-way platforms are ignored for, after dropping
+    /// through one.
+    pub drop_through_ticks: u32,
+    /// Which side the body ran into a wall on during its last move: -1 for
+    /// the left, 1 for the right and 0 for neither.
+    pub wall: f32,
+    /// The collision box, relative to the position of the body's instance.
+    pub hitbox: Aabb,
+}
+
+impl Body {
+    pub fn new(hitbox: Aabb, max_fall_speed: f32) -> Self {
+        Self {
+            velocity: Vec2::ZERO,
+            acceleration: Vec2::ZERO,
+            gravity_scale: 1.,
+            max_fall_speed,
+            grounded: false,
+            on_one_way: false,
+            drop_through_ticks: 0,
+            wall: 0.,
+            hitbox,
+        }
+    }
+
+    /// The hitbox in world space for a body at `position`.
+    pub fn bounds(&self, position: Vec2) -> Aabb {
+        self.hitbox.translate(position)
+    }
+
+    /// Applies gravity and acceleration for one step of `dt` seconds and
+    /// returns how far the body wants to move.
+    pub fn integrate(&mut self, gravity: f32, dt: f32) -> Vec2 {
+        let gravity = Vec2::new(0., gravity * self.gravity_scale);
+        self.velocity += (self.acceleration + gravity) * dt;
+        self.velocity.y = self.velocity.y.min(self.max_fall_speed);
+
+        self.velocity * dt
+    }
+
+    /// Stops the body along every axis it collided on and updates whether it
+    /// is standing on something.
+    pub fn apply_contacts(&mut self, contacts: &Contacts) {
+        if (contacts.floor && self.velocity.y > 0.) || (contacts.ceiling && self.velocity.y < 0.) {
+            self.velocity.y = 0.;
+        }
+
+        if (contacts.wall_left && self.velocity.x < 0.)
+            || (contacts.wall_right && self.velocity.x > 0.)
+        {
+            self.velocity.x = 0.;
+        }
+
+        self.grounded = contacts.floor;
+        self.on_one_way = contacts.one_way_floor;
+        self.wall = if contacts.wall_left {
+            -1.
+        } else if contacts.wall_right {
+            1.
+        } else {
+            0.
+        };
+    }
+}
+
+/// Describes a jump by how high it goes and how long it takes to reach the
+/// top, which is easier to tune than raw gravity and impulse values.
+#[derive(Clone, Copy)]
+pub struct JumpProfile {
+    pub jump_height: f32,
+    pub time_to_apex: f32,
+}
+
+impl JumpProfile {
+    /// The downward acceleration that makes a jump peak after `time_to_apex`.
+    pub fn gravity(&self) -> f32 {
+        2. * self.jump_height / (self.time_to_apex * self.time_to_apex)
+    }
+
+    /// The upward speed needed to reach `jump_height`.
+    pub fn jump_velocity(&self) -> f32 {
+        2. * self.jump_height / self.time_to_apex
+    }
+}
+
+impl Default for JumpProfile {
+    fn default() -> Self {
+        Self {
+            jump_height: 150.,
+            time_to_apex: 0.4,
+        }
+    }
+}
+
+/// Everything that decides how the player moves. The forgiveness windows are
+/// in seconds, and get rounded to whole ticks so they behave the same every
+/// time.
+#[derive(Clone, Copy)]
+pub struct MovementProfile {
+    /// Running speed, in pixels per second.
+    pub speed: f32,
+    /// How quickly the body speeds up and slows down towards the running
+    /// speed on the ground, in pixels per second squared.
+    pub run_acceleration: f32,
+    /// The same in the air, where steering is usually looser.
+    pub air_acceleration: f32,
+    pub jump: JumpProfile,
+    /// How long after running off a ledge a jump still works.
+    pub coyote_time: f32,
+    /// How long before landing a jump press is remembered for.
+    pub jump_buffer_time: f32,
+    /// What the upward speed is multiplied by when the jump is let go of
+    /// early, for shorter hops.
+    pub jump_cut: f32,
+    /// How slow the body has to be moving vertically to count as being at
+    /// the top of a jump, in pixels per second.
+    pub apex_threshold: f32,
+    /// What gravity is multiplied by at the top of a jump while it's held,
+    /// to hang there a little.
+    pub apex_gravity_scale: f32,
+    /// How long one-way platforms are ignored for after dropping through
+    /// one with down and jump.
+    pub drop_through_time: f32,
+    /// The fastest the body falls while sliding down a wall, in pixels per
+    /// second.
+    pub wall_slide_speed: f32,
+    /// The speed a wall jump pushes off with: `x` away from the wall and `y`
+    /// upwards, in pixels per second.
+    pub wall_jump: Vec2,
+    /// How long after a wall jump steering is ignored for, so the push-off
+    /// isn't cancelled straight away by still holding towards the wall.
+    pub wall_jump_lock_time: f32,
+    /// How far past a ledge's corner the top of the body can be and still
+    /// grab it, in pixels.
+    pub ledge_grab_reach: f32,
+    /// How long climbing up onto a ledge takes.
+    pub ledge_climb_time: f32,
+}
+
+impl MovementProfile {
+    /// `seconds` as a whole number of ticks of `dt` seconds.
+    pub fn ticks(seconds: f32, dt: f32) -> u32 {
+        (seconds / dt).round() as u32
+    }
+}
+
+impl Default for MovementProfile {
+    fn default() -> Self {
+        Self {
+            speed: 120.,
+            run_acceleration: 1800.,
+            air_acceleration: 1200.,
+            jump: JumpProfile::default(),
+            coyote_time: 0.1,
+            jump_buffer_time: 0.1,
+            jump_cut: 0.5,
+            apex_threshold: 40.,
+            apex_gravity_scale: 0.5,
+            drop_through_time: 0.2,
+            wall_slide_speed: 60.,
+            wall_jump: Vec2::new(160., 280.),
+            wall_jump_lock_time: 0.15,
+            ledge_grab_reach: 4.,
+            ledge_climb_time: 0.25,
+        }
+    }
+}
@@ -1,24 +1,66 @@
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Mat4, UVec2, Vec2, Vec3};
 use winit::dpi::PhysicalSize;
 
+use crate::{collision::Aabb, virtual_screen::Viewport};
+
+#[derive(Clone, Copy, Debug)]
 pub struct Camera {
     pub focus_position: Vec2,
     pub zoom: f32,
+    /// Clockwise, in radians. The world appears to turn the other way.
+    pub rotation: f32,
     pub window_size: PhysicalSize<u32>,
-    pub aspect_ratio: f32,
+    /// The size of the virtual screen the camera draws to, in pixels.
+    pub resolution: UVec2,
 }
 
 impl Camera {
+    /// How much of the world the camera shows.
+    pub fn view_size(&self) -> Vec2 {
+        self.resolution.as_vec2() / self.zoom
+    }
+
+    /// Converts a point in the window, in physical pixels, to the point in
+    /// the world under it.
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        let viewport = Viewport::fit(self.resolution, self.window_size);
+        let uv = viewport.to_virtual(point) / self.resolution.as_vec2();
+        let clip = Vec3::new(uv.x * 2. - 1., 1. - uv.y * 2., 0.);
+
+        self.build_view_projection_matrix()
+            .inverse()
+            .project_point3(clip)
+            .truncate()
+    }
+
+    /// Where the camera has to focus to show as much around `focus` as it
+    /// can without seeing past `bounds`. On any axis where the bounds are
+    /// smaller than the view, it centers on them instead.
+    pub fn clamp_focus(&self, focus: Vec2, bounds: &Aabb) -> Vec2 {
+        let half_view = self.view_size() / 2.;
+        let min = bounds.min + half_view;
+        let max = bounds.max - half_view;
+
+        Vec2::select(
+            min.cmple(max),
+            focus.clamp(min.min(max), max.max(min)),
+            bounds.center(),
+        )
+    }
+
     pub fn build_view_projection_matrix(&self) -> Mat4 {
-        let left = self.focus_position.x - self.window_size.width as f32 / 2.;
-        let right = self.focus_position.x + self.window_size.width as f32 / 2.;
-        let top = self.focus_position.y - self.window_size.height as f32 / 2.;
-        let bottom = self.focus_position.y + self.window_size.height as f32 / 2.;
+        // Snapping to whole pixels keeps sprites from shimmering as the
+        // camera moves
+        let focus = (self.focus_position * self.zoom).round() / self.zoom;
+        let half_view = self.view_size() / 2.;
 
-        let orth = Mat4::orthographic_rh(left, right, bottom, top, 0., 1.);
-        let zoom = Mat4::from_scale(Vec3::splat(self.zoom));
+        let projection =
+            Mat4::orthographic_rh(-half_view.x, half_view.x, half_view.y, -half_view.y, 0., 1.);
+        // Turning the camera turns the world the other way on screen
+        let view =
+            Mat4::from_rotation_z(-self.rotation) * Mat4::from_translation(-focus.extend(0.));
 
-        orth * zoom
+        projection * view
     }
 }
 
@@ -29,23 +71,318 @@ pub struct CameraUniform {
 }
 
 impl CameraUniform {
-    pub fn new(camera: &Camera, window_size: &PhysicalSize<u32>) -> Self {
+    pub fn new(camera: &Camera) -> Self {
         Self {
             view_proj: camera.build_view_projection_matrix(),
         }
     }
 }
 
+/// Makes a camera follow a target, e.g. the player.
 pub struct CameraController {
-    speed: f32,
-    direction: Vec2,
+    /// Half the size of the box around the camera's goal that the target can
+    /// move within without the camera following.
+    pub dead_zone: Vec2,
+    /// Roughly how long the camera takes to catch up with its goal, in
+    /// seconds. Zero snaps straight to it.
+    pub smooth_time: f32,
+    /// How many seconds of the target's movement to look ahead by.
+    pub look_ahead_time: f32,
+    /// The furthest the look-ahead can move the camera on each axis.
+    pub max_look_ahead: Vec2,
+    /// What the camera should never see past, usually the level's bounds.
+    pub bounds: Option<Aabb>,
+    goal: Vec2,
+    velocity: Vec2,
 }
 
 impl CameraController {
-    pub fn new(speed: f32) -> Self {
+    pub fn new(dead_zone: Vec2, smooth_time: f32) -> Self {
         Self {
-            speed,
-            direction: Vec2::ZERO,
+            dead_zone,
+            smooth_time,
+            look_ahead_time: 0.,
+            max_look_ahead: Vec2::ZERO,
+            bounds: None,
+            goal: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    /// Moves the camera straight to `target`, e.g. after spawning or
+    /// teleporting.
+    pub fn snap(&mut self, camera: &mut Camera, target: Vec2) {
+        self.goal = target;
+        self.velocity = Vec2::ZERO;
+        camera.focus_position = self.clamp(camera, target);
+    }
+
+    /// Moves the camera `dt` seconds closer to a target at `target` moving
+    /// with `target_velocity`.
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        target: Vec2,
+        target_velocity: Vec2,
+        dt: f32,
+    ) {
+        let look_ahead = (target_velocity * self.look_ahead_time)
+            .clamp(-self.max_look_ahead, self.max_look_ahead);
+        let point = target + look_ahead;
+
+        // Drag the goal along just enough to keep the point inside the dead
+        // zone
+        self.goal = self
+            .goal
+            .clamp(point - self.dead_zone, point + self.dead_zone);
+        self.goal = self.clamp(camera, self.goal);
+
+        let focus = if self.smooth_time > 0. {
+            self.smooth_damp(camera.focus_position, dt)
+        } else {
+            self.goal
+        };
+
+        let clamped = self.clamp(camera, focus);
+        // Don't keep pushing against the edge of the bounds
+        self.velocity = Vec2::select(clamped.cmpeq(focus), self.velocity, Vec2::ZERO);
+        camera.focus_position = clamped;
+    }
+
+    /// A critically damped spring towards the goal, so the camera eases in
+    /// without overshooting.
+    fn smooth_damp(&mut self, current: Vec2, dt: f32) -> Vec2 {
+        let omega = 2. / self.smooth_time;
+        let x = omega * dt;
+        // A cheap and accurate approximation of e^-x
+        let decay = 1. / (1. + x + 0.48 * x * x + 0.235 * x * x * x);
+
+        let offset = current - self.goal;
+        let temp = (self.velocity + omega * offset) * dt;
+        self.velocity = (self.velocity - omega * temp) * decay;
+
+        self.goal + (offset + temp) * decay
+    }
+
+    /// Keeps the camera's view inside `bounds`.
+    fn clamp(&self, camera: &Camera, focus: Vec2) -> Vec2 {
+        match &self.bounds {
+            Some(bounds) => camera.clamp_focus(focus, bounds),
+            None => focus,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use glam::Vec4Swizzles;
+    use winit::dpi::LogicalSize;
+
+    use super::*;
+
+    fn camera(window_size: PhysicalSize<u32>, zoom: f32) -> Camera {
+        Camera {
+            focus_position: Vec2::new(100., 50.),
+            zoom,
+            rotation: 0.,
+            window_size,
+            resolution: UVec2::new(320, 180),
+        }
+    }
+
+    /// Where `point` in the world ends up in the window, the inverse of
+    /// `screen_to_world`.
+    fn world_to_screen(camera: &Camera, point: Vec2) -> Vec2 {
+        let clip = camera.build_view_projection_matrix() * point.extend(0.).extend(1.);
+        let uv = Vec2::new(clip.x + 1., 1. - clip.y) / 2.;
+        let viewport = Viewport::fit(camera.resolution, camera.window_size);
+        viewport.position + uv * viewport.size
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.abs_diff_eq(b, 1e-3), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn projection_maps_the_view_to_clip_space() {
+        let camera = camera(PhysicalSize::new(1280, 720), 1.);
+        let matrix = camera.build_view_projection_matrix();
+
+        let top_left = matrix * Vec2::new(-60., -40.).extend(0.).extend(1.);
+        assert_near(top_left.xy(), Vec2::new(-1., 1.));
+        let bottom_right = matrix * Vec2::new(260., 140.).extend(0.).extend(1.);
+        assert_near(bottom_right.xy(), Vec2::new(1., -1.));
+    }
+
+    #[test]
+    fn zoom_shrinks_the_view() {
+        let camera = camera(PhysicalSize::new(1280, 720), 2.);
+        assert_eq!(camera.view_size(), Vec2::new(160., 90.));
+        assert_near(camera.screen_to_world(Vec2::ZERO), Vec2::new(20., 5.));
+        assert_near(
+            camera.screen_to_world(Vec2::new(1280., 720.)),
+            Vec2::new(180., 95.),
+        );
+    }
+
+    #[test]
+    fn screen_to_world_skips_the_bars() {
+        // Letterboxed, pillarboxed and not a whole multiple of the
+        // resolution
+        for window_size in [
+            PhysicalSize::new(1280, 1024),
+            PhysicalSize::new(2560, 1080),
+            PhysicalSize::new(1000, 700),
+        ] {
+            let camera = camera(window_size, 1.);
+            let viewport = Viewport::fit(camera.resolution, window_size);
+
+            assert_near(
+                camera.screen_to_world(viewport.position),
+                Vec2::new(-60., -40.),
+            );
+            assert_near(
+                camera.screen_to_world(viewport.position + viewport.size),
+                Vec2::new(260., 140.),
+            );
+            assert_near(
+                camera.screen_to_world(viewport.position + viewport.size / 2.),
+                camera.focus_position,
+            );
+        }
+    }
+
+    #[test]
+    fn screen_to_world_round_trips() {
+        let points = [
+            Vec2::new(100., 50.),
+            Vec2::new(-13.5, 7.25),
+            Vec2::new(250., 139.),
+        ];
+        for scale_factor in [1., 1.25, 1.5, 2.] {
+            for zoom in [0.5, 1., 1.5, 3.] {
+                for rotation in [0., 0.3, FRAC_PI_2] {
+                    let mut camera =
+                        camera(LogicalSize::new(800, 600).to_physical(scale_factor), zoom);
+                    camera.rotation = rotation;
+
+                    for point in points {
+                        let screen = world_to_screen(&camera, point);
+                        assert_near(camera.screen_to_world(screen), point);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn focus_snaps_to_whole_pixels() {
+        let mut snapped = camera(PhysicalSize::new(1280, 720), 2.);
+        snapped.focus_position = Vec2::new(100.2, 50.3);
+        let matrix = snapped.build_view_projection_matrix();
+
+        // At 2x zoom, half pixels are whole ones on screen
+        snapped.focus_position = Vec2::new(100., 50.5);
+        assert_eq!(matrix, snapped.build_view_projection_matrix());
+    }
+
+    const DT: f32 = 1. / 60.;
+
+    /// A controller following from `start`, with the camera snapped to it.
+    fn follow(controller: &mut CameraController, start: Vec2) -> Camera {
+        let mut camera = camera(PhysicalSize::new(1280, 720), 1.);
+        controller.snap(&mut camera, start);
+        camera
+    }
+
+    #[test]
+    fn camera_holds_still_while_the_target_is_in_the_dead_zone() {
+        let mut controller = CameraController::new(Vec2::new(16., 8.), 0.2);
+        let mut camera = follow(&mut controller, Vec2::new(100., 50.));
+
+        for i in 0..60 {
+            let target = Vec2::new(100., 50.) + Vec2::new(16., -8.) * (i as f32 / 59.);
+            controller.update_camera(&mut camera, target, Vec2::ZERO, DT);
+            assert_eq!(camera.focus_position, Vec2::new(100., 50.));
         }
+
+        // Leaving it drags the camera along
+        controller.update_camera(&mut camera, Vec2::new(120., 50.), Vec2::ZERO, DT);
+        assert!(camera.focus_position.x > 100.);
+    }
+
+    #[test]
+    fn spring_converges_without_overshooting() {
+        let mut controller = CameraController::new(Vec2::ZERO, 0.25);
+        let mut camera = follow(&mut controller, Vec2::ZERO);
+        let target = Vec2::new(200., -80.);
+
+        let mut previous = camera.focus_position;
+        for _ in 0..120 {
+            controller.update_camera(&mut camera, target, Vec2::ZERO, DT);
+            let focus = camera.focus_position;
+            assert!(focus.x >= previous.x && focus.x <= target.x, "{}", focus);
+            assert!(focus.y <= previous.y && focus.y >= target.y, "{}", focus);
+            previous = focus;
+        }
+
+        assert!(
+            camera.focus_position.abs_diff_eq(target, 0.5),
+            "{}",
+            camera.focus_position
+        );
+    }
+
+    #[test]
+    fn look_ahead_is_capped() {
+        let mut controller = CameraController::new(Vec2::ZERO, 0.);
+        controller.look_ahead_time = 0.5;
+        controller.max_look_ahead = Vec2::new(32., 16.);
+        let mut camera = follow(&mut controller, Vec2::ZERO);
+
+        controller.update_camera(&mut camera, Vec2::ZERO, Vec2::new(40., 0.), DT);
+        assert_eq!(camera.focus_position, Vec2::new(20., 0.));
+
+        controller.update_camera(&mut camera, Vec2::ZERO, Vec2::new(1000., -1000.), DT);
+        assert_eq!(camera.focus_position, Vec2::new(32., -16.));
+    }
+
+    #[test]
+    fn focus_clamps_to_the_bounds() {
+        let mut controller = CameraController::new(Vec2::ZERO, 0.25);
+        // The 320x180 view can move 40 pixels either way from the center
+        let bounds = Aabb::new(Vec2::ZERO, Vec2::new(400., 180.));
+        controller.bounds = Some(bounds);
+        let mut camera = follow(&mut controller, Vec2::new(200., 90.));
+
+        for _ in 0..120 {
+            controller.update_camera(&mut camera, Vec2::new(1000., 500.), Vec2::ZERO, DT);
+            assert!(camera.focus_position.x <= 240., "{}", camera.focus_position);
+            assert_eq!(camera.focus_position.y, 90.);
+        }
+        assert!((camera.focus_position.x - 240.).abs() < 0.01);
+    }
+
+    #[test]
+    fn hitting_the_bounds_stops_the_camera() {
+        let mut controller = CameraController::new(Vec2::ZERO, 0.25);
+        let mut camera = follow(&mut controller, Vec2::new(200., 90.));
+        for _ in 0..10 {
+            controller.update_camera(&mut camera, Vec2::new(1000., 90.), Vec2::ZERO, DT);
+        }
+        assert!(camera.focus_position.x > 240. && controller.velocity.x > 0.);
+
+        // Bounds that appear while the camera is moving, e.g. on entering a
+        // camera region, stop it at the edge rather than pushing against it
+        controller.bounds = Some(Aabb::new(Vec2::ZERO, Vec2::new(400., 180.)));
+        controller.update_camera(&mut camera, Vec2::new(1000., 90.), Vec2::ZERO, DT);
+        assert_eq!(camera.focus_position, Vec2::new(240., 90.));
+        assert_eq!(controller.velocity, Vec2::ZERO);
+
+        // so heading back starts straight away
+        controller.update_camera(&mut camera, Vec2::new(160., 90.), Vec2::ZERO, DT);
+        assert!(camera.focus_position.x < 240.);
     }
 }
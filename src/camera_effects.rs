@@ -0,0 +1,376 @@
+use glam::Vec2;
+
+use crate::{
+    camera::Camera,
+    collision::Aabb,
+    level::Level,
+    tween::{Easing, Tween},
+};
+
+/// Something layered on top of the camera, e.g. screen shake.
+///
+/// Effects are applied to a copy of the camera every frame, so they never
+/// feed back into whatever moves the camera itself.
+pub trait CameraEffect {
+    fn apply(&mut self, camera: &mut Camera, dt: f32);
+}
+
+/// Shakes the camera by an amount that follows the player's "trauma", which
+/// hits add to and which wears off over time.
+pub struct ScreenShake {
+    /// How much trauma wears off per second.
+    pub decay: f32,
+    /// The furthest the camera is moved at full trauma, in pixels.
+    pub max_offset: Vec2,
+    /// The furthest the camera is turned at full trauma, in radians.
+    pub max_rotation: f32,
+    /// How quickly the shake changes direction.
+    pub frequency: f32,
+    trauma: f32,
+    time: f32,
+}
+
+impl ScreenShake {
+    pub fn new(max_offset: Vec2, max_rotation: f32) -> Self {
+        Self {
+            decay: 1.,
+            max_offset,
+            max_rotation,
+            frequency: 20.,
+            trauma: 0.,
+            time: 0.,
+        }
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Adds to the trauma, which is capped at 1.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0., 1.);
+    }
+}
+
+impl CameraEffect for ScreenShake {
+    fn apply(&mut self, camera: &mut Camera, dt: f32) {
+        self.time += dt;
+
+        // Squaring makes small amounts of trauma barely noticeable and large
+        // ones violent
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.frequency;
+        let offset = Vec2::new(noise(0, t), noise(1, t)) * self.max_offset;
+
+        camera.focus_position += offset * shake;
+        camera.rotation += noise(2, t) * self.max_rotation * shake;
+
+        self.trauma = (self.trauma - self.decay * dt).max(0.);
+    }
+}
+
+/// Smoothly zooms the camera in and out, on top of its own zoom.
+pub struct ZoomTween {
+    tween: Tween,
+}
+
+impl ZoomTween {
+    pub fn new() -> Self {
+        Self {
+            tween: Tween::new(1., 1., 0., Easing::Linear),
+        }
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.tween.value()
+    }
+
+    /// Starts zooming from the current zoom to `zoom` over `duration` seconds.
+    pub fn zoom_to(&mut self, zoom: f32, duration: f32, easing: Easing) {
+        self.tween = Tween::new(self.zoom(), zoom, duration, easing);
+    }
+
+    /// Whether the zoom has reached what it was last told to zoom to.
+    pub fn is_finished(&self) -> bool {
+        self.tween.is_finished()
+    }
+}
+
+impl Default for ZoomTween {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraEffect for ZoomTween {
+    fn apply(&mut self, camera: &mut Camera, dt: f32) {
+        camera.zoom *= self.tween.update(dt);
+    }
+}
+
+/// An area of the level with a fixed framing. While the target is inside it
+/// the camera shows the framing instead of following.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraRegion {
+    pub bounds: Aabb,
+    pub focus_position: Vec2,
+    pub zoom: f32,
+    /// How long blending to and from the framing takes, in seconds. Zero
+    /// snaps straight to it.
+    pub blend_time: f32,
+}
+
+impl CameraRegion {
+    /// Builds a region for each of a level's `camera_region` objects, framed
+    /// on its center. The optional `zoom` and `blend_time` properties default
+    /// to 1 and 0.
+    pub fn from_level(level: &Level) -> Vec<Self> {
+        level
+            .objects_of_class("camera_region")
+            .map(|object| Self {
+                bounds: object.bounds(),
+                focus_position: object.bounds().center(),
+                zoom: object
+                    .property("zoom")
+                    .and_then(|zoom| zoom.as_f32())
+                    .unwrap_or(1.),
+                blend_time: object
+                    .property("blend_time")
+                    .and_then(|blend_time| blend_time.as_f32())
+                    .unwrap_or(0.),
+            })
+            .collect()
+    }
+}
+
+/// Switches the camera between following and the framing of whichever
+/// region the target is in.
+pub struct CameraRegions {
+    pub regions: Vec<CameraRegion>,
+    pub easing: Easing,
+    /// The point deciding which region is active, usually the player.
+    pub target: Vec2,
+    active: Option<usize>,
+    /// What the camera showed when the active region last changed, which
+    /// blends into what it should show now.
+    blend_from: Option<(Vec2, f32)>,
+    blend: Tween,
+    shown: Option<(Vec2, f32)>,
+}
+
+impl CameraRegions {
+    pub fn new(regions: Vec<CameraRegion>) -> Self {
+        Self {
+            regions,
+            easing: Easing::SineInOut,
+            target: Vec2::ZERO,
+            active: None,
+            blend_from: None,
+            blend: Tween::new(0., 1., 0., Easing::Linear),
+            shown: None,
+        }
+    }
+
+    pub fn active(&self) -> Option<&CameraRegion> {
+        self.active.map(|index| &self.regions[index])
+    }
+}
+
+impl CameraEffect for CameraRegions {
+    fn apply(&mut self, camera: &mut Camera, dt: f32) {
+        let active = self
+            .regions
+            .iter()
+            .position(|region| region.bounds.contains(self.target));
+
+        if active != self.active {
+            // Leaving a region blends back just like entering it
+            let blend_time = active
+                .or(self.active)
+                .map_or(0., |index| self.regions[index].blend_time);
+
+            self.active = active;
+            self.blend_from = self.shown;
+            self.blend = Tween::new(0., 1., blend_time, self.easing);
+        }
+
+        let (focus_position, zoom) = self
+            .active()
+            .map_or((camera.focus_position, camera.zoom), |region| {
+                (region.focus_position, region.zoom)
+            });
+
+        let t = self.blend.update(dt);
+        (camera.focus_position, camera.zoom) = match self.blend_from {
+            Some((from_position, from_zoom)) => (
+                from_position.lerp(focus_position, t),
+                from_zoom + (zoom - from_zoom) * t,
+            ),
+            None => (focus_position, zoom),
+        };
+
+        self.shown = Some((camera.focus_position, camera.zoom));
+    }
+}
+
+/// Every effect the game layers on top of its camera, applied in order:
+/// regions pick the framing, zooming scales it and shaking moves it around.
+pub struct CameraEffects {
+    pub regions: CameraRegions,
+    pub zoom: ZoomTween,
+    pub shake: ScreenShake,
+    /// What the camera should never see past once every effect is applied,
+    /// usually the level's bounds.
+    pub bounds: Option<Aabb>,
+}
+
+impl CameraEffects {
+    /// Returns `camera` with every effect applied.
+    pub fn apply(&mut self, camera: &Camera, dt: f32) -> Camera {
+        let mut camera = *camera;
+
+        let effects: [&mut dyn CameraEffect; 3] =
+            [&mut self.regions, &mut self.zoom, &mut self.shake];
+        for effect in effects {
+            effect.apply(&mut camera, dt);
+        }
+
+        if let Some(bounds) = &self.bounds {
+            camera.focus_position = camera.clamp_focus(camera.focus_position, bounds);
+        }
+
+        camera
+    }
+}
+
+/// Smooth noise from -1 to 1, with a different pattern for every `seed`.
+fn noise(seed: u32, t: f32) -> f32 {
+    let hash = |x: i32| {
+        let mut h = (x as u32).wrapping_mul(0x27d4_eb2d) ^ seed.wrapping_mul(0x1656_67b1);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h as f32 / u32::MAX as f32 * 2. - 1.
+    };
+
+    let i = t.floor();
+    let f = t - i;
+    // Smoothstep between random values at every whole number
+    let f = f * f * (3. - 2. * f);
+
+    let a = hash(i as i32);
+    let b = hash(i as i32 + 1);
+
+    a + (b - a) * f
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use glam::UVec2;
+    use winit::dpi::PhysicalSize;
+
+    use super::*;
+    use crate::tiled;
+
+    fn camera(focus_position: Vec2) -> Camera {
+        Camera {
+            focus_position,
+            zoom: 1.,
+            rotation: 0.,
+            window_size: PhysicalSize::new(1280, 720),
+            resolution: UVec2::new(320, 180),
+        }
+    }
+
+    fn view(camera: &Camera) -> Aabb {
+        let half_view = camera.view_size() / 2.;
+        Aabb::new(
+            camera.focus_position - half_view,
+            camera.focus_position + half_view,
+        )
+    }
+
+    fn effects(regions: Vec<CameraRegion>, bounds: Aabb) -> CameraEffects {
+        CameraEffects {
+            regions: CameraRegions::new(regions),
+            zoom: ZoomTween::new(),
+            shake: ScreenShake::new(Vec2::new(6., 4.), 0.),
+            bounds: Some(bounds),
+        }
+    }
+
+    #[test]
+    fn region_framing_stays_inside_the_bounds() {
+        let bounds = Aabb::new(Vec2::ZERO, Vec2::new(640., 368.));
+        let region = CameraRegion {
+            bounds: Aabb::new(Vec2::new(496., 208.), Vec2::new(640., 368.)),
+            focus_position: Vec2::new(568., 288.),
+            zoom: 1.5,
+            blend_time: 0.,
+        };
+        let mut effects = effects(vec![region], bounds);
+        effects.regions.target = Vec2::new(600., 300.);
+
+        let camera = effects.apply(&camera(Vec2::new(500., 280.)), 1. / 60.);
+        let view = view(&camera);
+        assert_eq!(camera.zoom, 1.5);
+        assert!(view.max.x <= bounds.max.x + 1e-3, "{:?}", view);
+        assert!(view.max.y <= bounds.max.y + 1e-3, "{:?}", view);
+    }
+
+    #[test]
+    fn shake_stays_inside_the_bounds() {
+        let bounds = Aabb::new(Vec2::ZERO, Vec2::new(640., 368.));
+        let mut effects = effects(Vec::new(), bounds);
+        effects.shake.add_trauma(1.);
+
+        for _ in 0..60 {
+            let camera = effects.apply(&camera(Vec2::new(160., 90.)), 1. / 60.);
+            let view = view(&camera);
+            assert!(view.min.cmpge(bounds.min - 1e-3).all(), "{:?}", view);
+        }
+    }
+
+    #[test]
+    fn zoom_reaches_its_target_after_the_duration() {
+        let mut zoom = ZoomTween::new();
+        zoom.zoom_to(2., 1., Easing::QuadInOut);
+
+        let mut zooms = Vec::new();
+        for _ in 0..4 {
+            let mut camera = camera(Vec2::ZERO);
+            camera.zoom = 1.5;
+            zoom.apply(&mut camera, 0.25);
+            zooms.push(camera.zoom / 1.5);
+        }
+
+        assert_eq!(zooms, [1.125, 1.5, 1.875, 2.]);
+        assert!(zoom.is_finished());
+
+        // Zooming again starts from wherever it got to
+        zoom.zoom_to(1., 0.5, Easing::Linear);
+        let mut camera = camera(Vec2::ZERO);
+        zoom.apply(&mut camera, 0.25);
+        assert_eq!(camera.zoom, 1.5);
+    }
+
+    #[test]
+    fn level_regions_fit_inside_their_level() {
+        let level = tiled::load_level(Path::new("resources/levels/level1.tmx")).unwrap();
+        let bounds = level.map.bounds();
+
+        for region in CameraRegion::from_level(&level) {
+            let mut camera = camera(region.focus_position);
+            camera.zoom = region.zoom;
+            let view = view(&camera);
+            assert!(
+                view.min.cmpge(bounds.min).all() && view.max.cmple(bounds.max).all(),
+                "region framing {:?} goes past the level {:?}",
+                view,
+                bounds
+            );
+        }
+    }
+}
@@ -0,0 +1,832 @@
+use glam::Vec2;
+
+/// How close two edges have to be to count as touching. Keeps floating point
+/// error from letting a body sink into, or snag on, the surface it rests on.
+const EPSILON: f32 = 0.01;
+
+/// An axis-aligned bounding box. Like the rest of the world, +y points down,
+/// so `min` is the top-left corner and `max` the bottom-right one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Aabb {
+    pub fn new(min: Vec2, max: Vec2) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_position_size(position: Vec2, size: Vec2) -> Self {
+        Self {
+            min: position,
+            max: position + size,
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.
+    }
+
+    pub fn translate(&self, offset: Vec2) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Whether `point` is inside the box, counting the top and left edges but
+    /// not the bottom and right ones.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.min.cmple(point).all() && self.max.cmpgt(point).all()
+    }
+
+    /// Whether the boxes overlap. Boxes that only share an edge don't.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && self.max.x > other.min.x
+            && self.min.y < other.max.y
+            && self.max.y > other.min.y
+    }
+
+    /// The smallest box containing both this box and this box moved by
+    /// `motion`.
+    pub fn swept(&self, motion: Vec2) -> Self {
+        let moved = self.translate(motion);
+        Self {
+            min: self.min.min(moved.min),
+            max: self.max.max(moved.max),
+        }
+    }
+}
+
+/// How a collider stops bodies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColliderKind {
+    /// Blocks from every side.
+    Solid,
+    /// Only blocks bodies landing on it from above, so it can be jumped up
+    /// through and dropped down through.
+    OneWay,
+    /// A ramp filling part of the bounds. `left` and `right` are how far its
+    /// surface is from its base at either edge. The base is the bottom edge,
+    /// or the top edge for ceilings.
+    Slope {
+        left: f32,
+        right: f32,
+        ceiling: bool,
+    },
+}
+
+/// A static shape bodies collide with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collider {
+    pub bounds: Aabb,
+    pub kind: ColliderKind,
+}
+
+impl Collider {
+    pub fn solid(bounds: Aabb) -> Self {
+        Self {
+            bounds,
+            kind: ColliderKind::Solid,
+        }
+    }
+
+    pub fn one_way(bounds: Aabb) -> Self {
+        Self {
+            bounds,
+            kind: ColliderKind::OneWay,
+        }
+    }
+
+    pub fn slope(bounds: Aabb, left: f32, right: f32, ceiling: bool) -> Self {
+        Self {
+            bounds,
+            kind: ColliderKind::Slope {
+                left,
+                right,
+                ceiling,
+            },
+        }
+    }
+
+    /// The height of the surface at `x`. That's the top edge for anything
+    /// but slopes.
+    pub fn surface_at(&self, x: f32) -> f32 {
+        let ColliderKind::Slope {
+            left,
+            right,
+            ceiling,
+        } = self.kind
+        else {
+            return self.bounds.min.y;
+        };
+
+        let t = ((x - self.bounds.min.x) / self.bounds.size().x).clamp(0., 1.);
+        let height = left + (right - left) * t;
+        if ceiling {
+            self.bounds.min.y + height
+        } else {
+            self.bounds.max.y - height
+        }
+    }
+
+    /// The part of the collider that stops a body moving sideways into it by
+    /// `dx`. Slopes only get in the way as far as their surface reaches at
+    /// the edge the body comes in from.
+    fn wall(&self, dx: f32) -> Aabb {
+        let ColliderKind::Slope { ceiling, .. } = self.kind else {
+            return self.bounds;
+        };
+
+        let edge = if dx > 0. {
+            self.bounds.min.x
+        } else {
+            self.bounds.max.x
+        };
+        let surface = self.surface_at(edge);
+        if ceiling {
+            Aabb::new(self.bounds.min, Vec2::new(self.bounds.max.x, surface))
+        } else {
+            Aabb::new(Vec2::new(self.bounds.min.x, surface), self.bounds.max)
+        }
+    }
+
+    fn spans(&self, x: f32) -> bool {
+        self.bounds.min.x <= x && x <= self.bounds.max.x
+    }
+}
+
+/// How a body moves through the world.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MoveOptions {
+    /// Ignore one-way colliders, to drop down through them.
+    pub drop_through: bool,
+    /// Keep to the ground, for bodies walking along it: step up onto slopes
+    /// and ledges up to half the body's width high, and follow slopes down
+    /// rather than running off into the air.
+    pub stick_to_ground: bool,
+}
+
+/// Which sides of a body touched something during a move. The names describe
+/// the surface that was hit, so `floor` means the contact normal points up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Contacts {
+    pub floor: bool,
+    pub ceiling: bool,
+    pub wall_left: bool,
+    pub wall_right: bool,
+    /// Whether everything the body landed on was one-way.
+    pub one_way_floor: bool,
+}
+
+impl Contacts {
+    pub fn on_wall(&self) -> bool {
+        self.wall_left || self.wall_right
+    }
+
+    pub fn any(&self) -> bool {
+        self.floor || self.ceiling || self.on_wall()
+    }
+}
+
+/// The result of moving a body through the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collision {
+    /// How far the body actually moved after being stopped by solids.
+    pub motion: Vec2,
+    pub contacts: Contacts,
+}
+
+/// Anything that static colliders can be looked up in.
+pub trait Colliders {
+    /// Pushes every collider that may overlap `region` into `out`.
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>);
+}
+
+impl Colliders for [Aabb] {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        out.extend(
+            self.iter()
+                .filter(|solid| solid.intersects(region))
+                .map(|solid| Collider::solid(*solid)),
+        );
+    }
+}
+
+impl Colliders for Vec<Aabb> {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        self.as_slice().colliders_in(region, out);
+    }
+}
+
+impl Colliders for [Collider] {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        out.extend(
+            self.iter()
+                .filter(|collider| collider.bounds.intersects(region)),
+        );
+    }
+}
+
+impl Colliders for Vec<Collider> {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        self.as_slice().colliders_in(region, out);
+    }
+}
+
+/// Both sets of colliders, e.g. the level and whatever is moving around in
+/// it.
+impl<A: Colliders + ?Sized, B: Colliders + ?Sized> Colliders for (&A, &B) {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        self.0.colliders_in(region, out);
+        self.1.colliders_in(region, out);
+    }
+}
+
+/// Moves `bounds` by `motion`, stopping at the first collider in the way.
+/// The horizontal axis is resolved before the vertical one, so a body running
+/// into a wall still falls and a body landing on a floor still slides.
+///
+/// One-way colliders only stop bodies that start out above them and are
+/// moving down. Slopes hold bodies up by the middle of their bottom edge, and
+/// push them down by the middle of their top edge.
+pub fn move_and_collide<C: Colliders + ?Sized>(
+    bounds: &Aabb,
+    motion: Vec2,
+    colliders: &C,
+    options: MoveOptions,
+) -> Collision {
+    let (step, snap) = if options.stick_to_ground {
+        // Far enough to follow a 45° slope down
+        (bounds.size().x / 2., motion.x.abs() + EPSILON)
+    } else {
+        (0., 0.)
+    };
+
+    let mut found = Vec::new();
+    let region = bounds.swept(motion);
+    colliders.colliders_in(
+        &Aabb::new(
+            region.min - EPSILON,
+            region.max + Vec2::new(EPSILON, EPSILON + snap),
+        ),
+        &mut found,
+    );
+
+    let mut solids = Vec::new();
+    let mut floors = Vec::new();
+    let mut ceilings = Vec::new();
+    for collider in &found {
+        match collider.kind {
+            ColliderKind::Solid => solids.push(collider.bounds),
+            // One-way colliders don't stop anything the body is already
+            // overlapping
+            ColliderKind::OneWay => {
+                if !options.drop_through && bounds.max.y <= collider.bounds.min.y + EPSILON {
+                    floors.push(*collider);
+                }
+            }
+            ColliderKind::Slope { ceiling: false, .. } => floors.push(*collider),
+            ColliderKind::Slope { ceiling: true, .. } => ceilings.push(*collider),
+        }
+    }
+
+    let mut contacts = Contacts::default();
+
+    // Anything low enough to step up onto is left to the ground check below
+    let walls: Vec<Aabb> = found
+        .iter()
+        .filter(|collider| collider.kind != ColliderKind::OneWay)
+        .map(|collider| collider.wall(motion.x))
+        .filter(|wall| step == 0. || wall.min.y < bounds.max.y - step - EPSILON)
+        .collect();
+    let mut dx = sweep_x(bounds, motion.x, &walls);
+    if dx != motion.x {
+        contacts.wall_left = motion.x < 0.;
+        contacts.wall_right = motion.x > 0.;
+    }
+    let shifted = bounds.translate(Vec2::new(dx, 0.));
+
+    // How far into a surface the body can have ended up during this move
+    let reach = step.max(motion.x.abs()) + motion.y.abs() + EPSILON;
+
+    let mut dy = move_y(
+        &shifted,
+        motion.y,
+        &solids,
+        &floors,
+        reach,
+        snap,
+        &mut contacts,
+    );
+
+    let moved = shifted.translate(Vec2::new(0., dy));
+    let x = moved.center().x;
+    let ceiling = ceilings
+        .iter()
+        .filter(|collider| collider.spans(x))
+        .map(|collider| collider.surface_at(x))
+        .filter(|surface| *surface > moved.min.y && *surface <= moved.min.y + reach)
+        .max_by(f32::total_cmp);
+    if let Some(surface) = ceiling {
+        if motion.y < 0. {
+            dy += surface - moved.min.y;
+            contacts.ceiling = true;
+        } else {
+            // Walking under a ceiling that gets lower is like walking into a
+            // wall
+            contacts.wall_left = dx < 0.;
+            contacts.wall_right = dx > 0.;
+            dx = 0.;
+
+            // The floor under the body where it stopped can be a different
+            // height to the one under where it would have been
+            contacts.floor = false;
+            contacts.one_way_floor = false;
+            dy = move_y(
+                bounds,
+                motion.y,
+                &solids,
+                &floors,
+                reach,
+                snap,
+                &mut contacts,
+            );
+        }
+    }
+
+    Collision {
+        motion: Vec2::new(dx, dy),
+        contacts,
+    }
+}
+
+/// Looks for the top corner of a wall on `side` of `bounds` (-1 for the left,
+/// 1 for the right) that's at most `reach` above the top of `bounds` and that
+/// a body the size of `bounds` could stand on. Returns where the corner is.
+pub fn find_ledge<C: Colliders + ?Sized>(
+    bounds: &Aabb,
+    side: f32,
+    reach: f32,
+    colliders: &C,
+) -> Option<Vec2> {
+    let x = if side > 0. {
+        bounds.max.x
+    } else {
+        bounds.min.x
+    };
+    let probe = Aabb::new(
+        Vec2::new(x.min(x + side), bounds.min.y - reach - EPSILON),
+        Vec2::new(x.max(x + side), bounds.min.y + EPSILON),
+    );
+    let mut found = Vec::new();
+    colliders.colliders_in(&probe, &mut found);
+
+    let size = bounds.size();
+    let mut corners: Vec<Vec2> = found
+        .iter()
+        .filter(|collider| collider.kind == ColliderKind::Solid)
+        .filter(|collider| {
+            collider.bounds.min.y >= probe.min.y
+                && collider.bounds.min.y <= probe.max.y
+                && if side > 0. {
+                    collider.bounds.min.x <= x + EPSILON && collider.bounds.max.x > x + EPSILON
+                } else {
+                    collider.bounds.max.x >= x - EPSILON && collider.bounds.min.x < x - EPSILON
+                }
+        })
+        .map(|collider| {
+            let corner_x = if side > 0. {
+                collider.bounds.min.x
+            } else {
+                collider.bounds.max.x
+            };
+            Vec2::new(corner_x, collider.bounds.min.y)
+        })
+        .collect();
+    corners.sort_by(|a, b| a.y.total_cmp(&b.y));
+
+    // The corner only counts as a ledge if there's room to stand on it
+    corners.into_iter().find(|corner| {
+        let min_x = if side > 0. {
+            corner.x
+        } else {
+            corner.x - size.x
+        };
+        let space = Aabb::new(
+            Vec2::new(min_x, corner.y - size.y) + EPSILON,
+            Vec2::new(min_x + size.x, corner.y) - EPSILON,
+        );
+
+        let mut blocking = Vec::new();
+        colliders.colliders_in(&space, &mut blocking);
+        !blocking.iter().any(|collider| {
+            collider.kind != ColliderKind::OneWay && collider.bounds.intersects(&space)
+        })
+    })
+}
+
+/// Moves `bounds` by `dy` after the horizontal motion has been resolved,
+/// stopping at `solids` and landing on `floors`. Near enough a floor below,
+/// the body is snapped onto it, up to `reach` and down to `snap`.
+fn move_y(
+    bounds: &Aabb,
+    dy: f32,
+    solids: &[Aabb],
+    floors: &[Collider],
+    reach: f32,
+    snap: f32,
+    contacts: &mut Contacts,
+) -> f32 {
+    let one_way: Vec<Aabb> = floors
+        .iter()
+        .filter(|collider| collider.kind == ColliderKind::OneWay)
+        .map(|collider| collider.bounds)
+        .collect();
+    let solid_dy = sweep_y(bounds, dy, solids);
+    let mut moved_dy = if dy > 0. {
+        sweep_y(bounds, solid_dy, &one_way)
+    } else {
+        solid_dy
+    };
+    if moved_dy != dy {
+        contacts.ceiling = dy < 0.;
+        contacts.floor = dy > 0.;
+        contacts.one_way_floor = contacts.floor && solid_dy == dy;
+    }
+
+    if dy >= 0. {
+        let moved = bounds.translate(Vec2::new(0., moved_dy));
+        let x = moved.center().x;
+
+        // The highest surface under the middle of the body that it has sunk
+        // into, or is close enough above to snap down onto
+        let ground = solids
+            .iter()
+            .map(|solid| Collider::solid(*solid))
+            .chain(floors.iter().copied())
+            .filter(|collider| collider.spans(x))
+            .map(|collider| (collider.surface_at(x), collider.kind))
+            .filter(|(surface, _)| {
+                *surface >= moved.max.y - reach && *surface <= moved.max.y + snap
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        if let Some((surface, kind)) = ground {
+            moved_dy += surface - moved.max.y;
+            contacts.floor = true;
+            contacts.one_way_floor = kind == ColliderKind::OneWay;
+        }
+    }
+
+    moved_dy
+}
+
+fn sweep_x(bounds: &Aabb, mut dx: f32, solids: &[Aabb]) -> f32 {
+    for solid in solids {
+        if bounds.max.y <= solid.min.y + EPSILON || bounds.min.y >= solid.max.y - EPSILON {
+            continue;
+        }
+
+        if dx > 0. && bounds.max.x <= solid.min.x + EPSILON {
+            dx = dx.min(solid.min.x - bounds.max.x);
+        } else if dx < 0. && bounds.min.x >= solid.max.x - EPSILON {
+            dx = dx.max(solid.max.x - bounds.min.x);
+        }
+    }
+
+    dx
+}
+
+fn sweep_y(bounds: &Aabb, mut dy: f32, solids: &[Aabb]) -> f32 {
+    for solid in solids {
+        if bounds.max.x <= solid.min.x + EPSILON || bounds.min.x >= solid.max.x - EPSILON {
+            continue;
+        }
+
+        if dy > 0. && bounds.max.y <= solid.min.y + EPSILON {
+            dy = dy.min(solid.min.y - bounds.max.y);
+        } else if dy < 0. && bounds.min.y >= solid.max.y - EPSILON {
+            dy = dy.max(solid.max.y - bounds.min.y);
+        }
+    }
+
+    dy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALK: MoveOptions = MoveOptions {
+        drop_through: false,
+        stick_to_ground: true,
+    };
+
+    /// An 8x8 body with its top-left corner at `x`, `y`.
+    fn body(x: f32, y: f32) -> Aabb {
+        Aabb::from_position_size(Vec2::new(x, y), Vec2::splat(8.))
+    }
+
+    fn tile(x: f32, y: f32) -> Aabb {
+        Aabb::from_position_size(Vec2::new(x, y), Vec2::splat(16.))
+    }
+
+    /// A 16x16 slope rising to the right from the bottom-left corner.
+    fn slope_up(x: f32, y: f32) -> Collider {
+        Collider::slope(tile(x, y), 0., 16., false)
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{} != {}", a, b);
+    }
+
+    fn collide<C: Colliders + ?Sized>(bounds: &Aabb, motion: Vec2, colliders: &C) -> Collision {
+        move_and_collide(bounds, motion, colliders, MoveOptions::default())
+    }
+
+    #[test]
+    fn free_motion_is_unchanged() {
+        let collision = collide(&body(0., 0.), Vec2::new(3., -5.), &vec![tile(32., 32.)]);
+
+        assert_eq!(collision.motion, Vec2::new(3., -5.));
+        assert!(!collision.contacts.any());
+    }
+
+    #[test]
+    fn landing_stops_flush_with_the_floor() {
+        let collision = collide(&body(4., 4.), Vec2::new(0., 10.), &vec![tile(0., 16.)]);
+
+        assert_eq!(collision.motion, Vec2::new(0., 4.));
+        assert!(collision.contacts.floor);
+        assert!(!collision.contacts.on_wall());
+    }
+
+    #[test]
+    fn falling_onto_the_floor_already_touching_it_is_grounded() {
+        let collision = collide(&body(4., 8.), Vec2::new(0., 0.5), &vec![tile(0., 16.)]);
+
+        assert_eq!(collision.motion, Vec2::ZERO);
+        assert!(collision.contacts.floor);
+    }
+
+    #[test]
+    fn jumping_stops_at_the_ceiling() {
+        let collision = collide(&body(4., 20.), Vec2::new(0., -10.), &vec![tile(0., 0.)]);
+
+        assert_eq!(collision.motion, Vec2::new(0., -4.));
+        assert!(collision.contacts.ceiling);
+        assert!(!collision.contacts.floor);
+    }
+
+    #[test]
+    fn walls_stop_flush_and_set_the_side() {
+        let walls = vec![tile(0., 0.), tile(32., 0.)];
+
+        let right = collide(&body(20., 4.), Vec2::new(6., 0.), &walls);
+        assert_eq!(right.motion, Vec2::new(4., 0.));
+        assert!(right.contacts.wall_right && !right.contacts.wall_left);
+
+        let left = collide(&body(20., 4.), Vec2::new(-6., 0.), &walls);
+        assert_eq!(left.motion, Vec2::new(-4., 0.));
+        assert!(left.contacts.wall_left && !left.contacts.wall_right);
+
+        // Pushing against a wall already touching it
+        let pushing = collide(&body(24., 4.), Vec2::new(2., 0.), &walls);
+        assert_eq!(pushing.motion, Vec2::ZERO);
+        assert!(pushing.contacts.wall_right);
+    }
+
+    #[test]
+    fn sliding_down_a_wall_keeps_falling() {
+        let walls = vec![tile(16., 0.), tile(16., 16.)];
+        let collision = collide(&body(8., 0.), Vec2::new(3., 5.), &walls);
+
+        assert_eq!(collision.motion, Vec2::new(0., 5.));
+        assert!(collision.contacts.wall_right);
+        assert!(!collision.contacts.floor);
+    }
+
+    #[test]
+    fn running_along_a_floor_of_tiles_doesnt_snag_on_their_edges() {
+        let floor: Vec<Aabb> = (0..4).map(|x| tile(x as f32 * 16., 16.)).collect();
+        let mut bounds = body(2., 8.);
+
+        for _ in 0..20 {
+            let collision = collide(&bounds, Vec2::new(2.5, 1.), &floor);
+            assert_eq!(collision.motion, Vec2::new(2.5, 0.));
+            assert!(collision.contacts.floor);
+            assert!(!collision.contacts.on_wall());
+            bounds = bounds.translate(collision.motion);
+        }
+    }
+
+    #[test]
+    fn sliding_along_a_wall_of_tiles_doesnt_snag_on_their_edges() {
+        let wall: Vec<Aabb> = (0..4).map(|y| tile(16., y as f32 * 16.)).collect();
+        let mut bounds = body(8., 2.);
+
+        for _ in 0..20 {
+            let collision = collide(&bounds, Vec2::new(1., 2.5), &wall);
+            assert_eq!(collision.motion, Vec2::new(0., 2.5));
+            assert!(collision.contacts.wall_right);
+            bounds = bounds.translate(collision.motion);
+        }
+    }
+
+    #[test]
+    fn moving_diagonally_onto_a_corner_lands_on_it() {
+        // Horizontal motion is resolved first, so the body ends up over the
+        // block before it comes down
+        let collision = collide(&body(0., 0.), Vec2::new(4., 4.), &vec![tile(10., 10.)]);
+
+        assert_eq!(collision.motion, Vec2::new(4., 2.));
+        assert!(collision.contacts.floor);
+        assert!(!collision.contacts.on_wall());
+    }
+
+    #[test]
+    fn moving_diagonally_past_a_corner_misses_it() {
+        let collision = collide(&body(0., 0.), Vec2::new(2., 4.), &vec![tile(10., 10.)]);
+
+        assert_eq!(collision.motion, Vec2::new(2., 4.));
+        assert!(!collision.contacts.any());
+    }
+
+    #[test]
+    fn moving_diagonally_into_an_inside_corner_stops_on_both_axes() {
+        let corner = vec![tile(0., 16.), tile(16., 0.), tile(16., 16.)];
+        let collision = collide(&body(4., 4.), Vec2::new(10., 10.), &corner);
+
+        assert_eq!(collision.motion, Vec2::new(4., 4.));
+        assert!(collision.contacts.wall_right);
+        assert!(collision.contacts.floor);
+    }
+
+    #[test]
+    fn fast_bodies_dont_tunnel_through_thin_walls() {
+        let thin = vec![
+            Aabb::new(Vec2::new(100., -50.), Vec2::new(101., 50.)),
+            Aabb::new(Vec2::new(-50., 100.), Vec2::new(50., 101.)),
+        ];
+
+        let across = collide(&body(0., 0.), Vec2::new(1000., 0.), &thin);
+        assert_eq!(across.motion, Vec2::new(92., 0.));
+        assert!(across.contacts.wall_right);
+
+        let down = collide(&body(0., 0.), Vec2::new(0., 1000.), &thin);
+        assert_eq!(down.motion, Vec2::new(0., 92.));
+        assert!(down.contacts.floor);
+    }
+
+    #[test]
+    fn fast_bodies_stop_at_the_nearest_wall() {
+        let walls = vec![tile(200., 0.), tile(40., 0.), tile(120., 0.)];
+        let collision = collide(&body(0., 4.), Vec2::new(500., 0.), &walls);
+
+        assert_eq!(collision.motion, Vec2::new(32., 0.));
+    }
+
+    #[test]
+    fn one_way_platforms_only_stop_bodies_from_above() {
+        let platform = vec![Collider::one_way(tile(0., 16.))];
+
+        let landing = collide(&body(4., 4.), Vec2::new(0., 10.), &platform);
+        assert_eq!(landing.motion, Vec2::new(0., 4.));
+        assert!(landing.contacts.floor && landing.contacts.one_way_floor);
+
+        let jumping = collide(&body(4., 34.), Vec2::new(0., -20.), &platform);
+        assert_eq!(jumping.motion, Vec2::new(0., -20.));
+        assert!(!jumping.contacts.any());
+
+        // Partway through at the top of a jump, it carries on falling
+        let inside = collide(&body(4., 14.), Vec2::new(0., 4.), &platform);
+        assert_eq!(inside.motion, Vec2::new(0., 4.));
+        assert!(!inside.contacts.floor);
+
+        let sideways = collide(&body(-10., 20.), Vec2::new(10., 0.), &platform);
+        assert_eq!(sideways.motion, Vec2::new(10., 0.));
+        assert!(!sideways.contacts.on_wall());
+    }
+
+    #[test]
+    fn dropping_through_ignores_one_way_platforms() {
+        let platform = vec![Collider::one_way(tile(0., 16.))];
+        let options = MoveOptions {
+            drop_through: true,
+            ..MoveOptions::default()
+        };
+        let collision = move_and_collide(&body(4., 8.), Vec2::new(0., 4.), &platform, options);
+
+        assert_eq!(collision.motion, Vec2::new(0., 4.));
+        assert!(!collision.contacts.floor);
+    }
+
+    #[test]
+    fn solid_floors_win_over_one_way_platforms_below_them() {
+        let colliders = vec![
+            Collider::solid(tile(0., 16.)),
+            Collider::one_way(tile(0., 24.)),
+        ];
+        let collision = collide(&body(4., 4.), Vec2::new(0., 30.), &colliders);
+
+        assert_eq!(collision.motion, Vec2::new(0., 4.));
+        assert!(collision.contacts.floor && !collision.contacts.one_way_floor);
+    }
+
+    #[test]
+    fn standing_still_on_the_floor_is_grounded() {
+        let collision = collide(&body(4., 8.), Vec2::ZERO, &vec![tile(0., 16.)]);
+
+        assert_eq!(collision.motion, Vec2::ZERO);
+        assert!(collision.contacts.floor);
+    }
+
+    #[test]
+    fn slopes_hold_bodies_up_by_the_middle() {
+        // Middle at x = 8, where the surface is halfway up
+        let collision = collide(&body(4., 0.), Vec2::new(0., 30.), &vec![slope_up(0., 16.)]);
+
+        assert_near(collision.motion, Vec2::new(0., 16.));
+        assert!(collision.contacts.floor);
+        assert!(!collision.contacts.one_way_floor);
+    }
+
+    #[test]
+    fn walking_up_and_down_a_slope_follows_its_surface() {
+        let slope = vec![slope_up(0., 16.)];
+        let start = body(4., 16.);
+
+        let up = move_and_collide(&start, Vec2::new(2., 1.), &slope, WALK);
+        assert_near(up.motion, Vec2::new(2., -2.));
+        assert!(up.contacts.floor && !up.contacts.on_wall());
+
+        let down = move_and_collide(&start, Vec2::new(-2., 1.), &slope, WALK);
+        assert_near(down.motion, Vec2::new(-2., 2.));
+        assert!(down.contacts.floor);
+
+        // Without sticking to the ground, walking down runs off into the air
+        let airborne = collide(&start, Vec2::new(-2., 0.), &slope);
+        assert_near(airborne.motion, Vec2::new(-2., 0.));
+        assert!(!airborne.contacts.floor);
+    }
+
+    #[test]
+    fn walking_onto_a_slope_from_flat_ground_steps_up() {
+        let colliders = vec![Collider::solid(tile(-16., 32.)), slope_up(0., 16.)];
+        let mut bounds = body(-12., 24.);
+
+        for _ in 0..8 {
+            let collision = move_and_collide(&bounds, Vec2::new(2., 1.), &colliders, WALK);
+            assert!(collision.contacts.floor);
+            assert!(!collision.contacts.on_wall());
+            bounds = bounds.translate(collision.motion);
+        }
+
+        // Middle at x = 8
+        assert_near(bounds.min, Vec2::new(4., 16.));
+    }
+
+    #[test]
+    fn the_high_side_of_a_slope_is_a_wall() {
+        // Coming from the right, into the full height edge
+        let collision = collide(
+            &body(18., 24.),
+            Vec2::new(-4., 0.),
+            &vec![slope_up(0., 16.)],
+        );
+
+        assert_eq!(collision.motion, Vec2::new(-2., 0.));
+        assert!(collision.contacts.wall_left);
+    }
+
+    #[test]
+    fn walking_up_a_slope_into_a_low_ceiling_stays_on_the_ground() {
+        // A ceiling that only leaves room where the slope is at its lowest
+        let colliders = vec![
+            slope_up(0., 16.),
+            Collider::slope(tile(0., 0.), 15., 15., true),
+        ];
+        let collision = move_and_collide(&body(4., 16.), Vec2::new(2., 1.), &colliders, WALK);
+
+        assert_near(collision.motion, Vec2::ZERO);
+        assert!(collision.contacts.wall_right);
+        assert!(collision.contacts.floor);
+    }
+
+    #[test]
+    fn jumping_into_a_ceiling_slope_stops_at_its_surface() {
+        // Low on the left, so the surface is at y = 8 under the middle
+        let ceiling = vec![Collider::slope(tile(0., 0.), 16., 0., true)];
+        let collision = collide(&body(4., 20.), Vec2::new(0., -16.), &ceiling);
+
+        assert_near(collision.motion, Vec2::new(0., -12.));
+        assert!(collision.contacts.ceiling);
+    }
+}
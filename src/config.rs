@@ -0,0 +1,104 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Something the player can change that's kept between runs in a TOML file.
+pub trait ConfigFile: Default + Serialize + DeserializeOwned {
+    /// What the file holds, e.g. "settings", to say in messages about it.
+    const DESCRIPTION: &'static str;
+
+    /// Checks values that parse but don't make sense together.
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Reads the TOML file at `path`.
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| {
+            format!(
+                "failed to read {} from {}",
+                Self::DESCRIPTION,
+                path.display()
+            )
+        })?;
+        let config: Self = toml::from_str(&text).with_context(|| {
+            format!(
+                "failed to parse {} in {}",
+                Self::DESCRIPTION,
+                path.display()
+            )
+        })?;
+        config
+            .check()
+            .with_context(|| format!("invalid {} in {}", Self::DESCRIPTION, path.display()))?;
+        Ok(config)
+    }
+
+    /// Reads `path`, falling back to the defaults if it doesn't exist or
+    /// can't be read.
+    fn load_or_default(path: &Path) -> Self {
+        if !path.exists() {
+            return Self::default();
+        }
+
+        Self::load(path).unwrap_or_else(|e| {
+            tracing::warn!("{:#}, using the default {}", e, Self::DESCRIPTION);
+            Self::default()
+        })
+    }
+
+    /// Writes the TOML file at `path`.
+    fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self)?;
+        fs::write(path, text)
+            .with_context(|| format!("failed to save {} to {}", Self::DESCRIPTION, path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use winit::event::VirtualKeyCode;
+
+    use super::*;
+    use crate::{
+        input::{Action, Bindings},
+        settings::Settings,
+    };
+
+    /// A path in the temporary directory unique to this test run.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("plat4rs-{}-{}.toml", name, std::process::id()))
+    }
+
+    #[test]
+    fn saved_files_load_the_same() {
+        let path = temp_path("bindings");
+        let mut bindings = Bindings::default();
+        bindings.rebind(Action::Jump, VirtualKeyCode::K);
+
+        bindings.save(&path).unwrap();
+        assert_eq!(Bindings::load(&path).unwrap(), bindings);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_or_invalid_files_fall_back_to_the_defaults() {
+        let path = temp_path("settings");
+        assert_eq!(Settings::load_or_default(&path), Settings::default());
+
+        fs::write(&path, "msaa_samples = 3").unwrap();
+        let error = Settings::load(&path).unwrap_err();
+        assert!(
+            error.to_string().starts_with("invalid settings in"),
+            "{}",
+            error
+        );
+        assert_eq!(Settings::load_or_default(&path), Settings::default());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
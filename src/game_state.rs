@@ -1,159 +1,194 @@
-use std::{collections::HashSet, time::Instant};
-
-use glam::Vec2;
-use wgpu::{
-    util::{BufferInitDescriptor, DeviceExt},
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, Device,
-    ShaderStages,
-};
-use winit::{dpi::PhysicalSize, event::VirtualKeyCode};
+use std::{path::Path, time::Instant};
+
+use glam::{UVec2, Vec2};
+use winit::dpi::PhysicalSize;
 
 use crate::{
-    camera::{Camera, CameraController, CameraUniform},
+    camera::{Camera, CameraController},
+    camera_effects::{CameraEffects, CameraRegion, CameraRegions, ScreenShake, ZoomTween},
+    config::ConfigFile,
+    gamepad::{GamepadSource, Gamepads},
+    input::{Bindings, Keyboard},
     instance::Instance,
-    model::{Mesh, Model, ModelController, ModelVertex},
+    replay::Recorder,
+    timestep::FixedTimestep,
+    tween::Easing,
+    world::{World, WorldEvent},
 };
 
+/// Where the player's key bindings are kept.
+pub const BINDINGS_PATH: &str = "bindings.toml";
+
+/// How far the camera zooms in when the player is sent back to the spawn
+/// point, before settling back out.
+const RESPAWN_ZOOM: f32 = 1.25;
+
+/// Real gamepads, when built with gilrs support and it starts up.
+#[cfg(feature = "gilrs")]
+fn default_gamepad_source() -> Option<Box<dyn GamepadSource>> {
+    match crate::gamepad::GilrsSource::new() {
+        Ok(source) => Some(Box::new(source)),
+        Err(e) => {
+            tracing::warn!("{:#}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "gilrs"))]
+fn default_gamepad_source() -> Option<Box<dyn GamepadSource>> {
+    None
+}
+
+/// A camera looking straight at the player, and the controller that keeps it
+/// following them.
+pub fn player_camera(
+    world: &World,
+    window_size: PhysicalSize<u32>,
+    resolution: UVec2,
+) -> (Camera, CameraController) {
+    let mut camera = Camera {
+        focus_position: world.level.map.bounds().center(),
+        zoom: 1.,
+        rotation: 0.,
+        window_size,
+        resolution,
+    };
+
+    let mut camera_controller = CameraController::new(Vec2::new(24., 32.), 0.25);
+    camera_controller.look_ahead_time = 0.4;
+    camera_controller.max_look_ahead = Vec2::new(48., 0.);
+    camera_controller.bounds = Some(world.level.map.bounds());
+    camera_controller.snap(&mut camera, world.instance.bounds().center());
+
+    (camera, camera_controller)
+}
+
+/// Everything about the running game apart from drawing it: the world, the
+/// camera looking at it and the input driving it.
 pub struct GameState {
     pub start_time: Instant,
     pub last_update: Instant,
+    pub timestep: FixedTimestep,
     pub camera: Camera,
     pub camera_controller: CameraController,
-    pub camera_uniform: CameraUniform,
-    pub camera_buffer: Buffer,
-    pub camera_bind_group: BindGroup,
-    pub camera_bind_group_layout: BindGroupLayout,
-    pub model: Model,
-    pub model_controller: ModelController,
-    pub instance: Instance,
-    pub instance_buffer: Buffer,
-    pub pressed_keys: HashSet<VirtualKeyCode>,
+    pub camera_effects: CameraEffects,
+    /// The camera with its effects applied, which is what gets drawn.
+    pub view_camera: Camera,
+    pub world: World,
+    pub keyboard: Keyboard,
+    pub gamepads: Gamepads,
+    /// Where gamepad events come from, if gamepads are supported at all.
+    pub gamepad_source: Option<Box<dyn GamepadSource>>,
+    pub bindings: Bindings,
+    /// Records every tick's input, when recording a replay.
+    pub recorder: Option<Recorder>,
 }
 
 impl GameState {
-    pub fn new(device: &Device, window_size: &PhysicalSize<u32>) -> Self {
+    pub fn new(window_size: &PhysicalSize<u32>, resolution: UVec2, world: World) -> Self {
         let start_time = Instant::now();
         let last_update = Instant::now();
 
-        let camera = Camera {
-            focus_position: Vec2::new(0., 0.),
-            zoom: 1.,
-            window_size: window_size.clone(),
-            aspect_ratio: 3. / 4.,
-        };
-
-        let camera_controller = CameraController::new(1.);
-
-        let camera_uniform = CameraUniform::new(&camera, window_size);
-        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Camera Buffer"),
-            contents: bytemuck::cast_slice(&[camera_uniform]),
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        });
-
-        let camera_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("camera_bind_group_layout"),
-            });
-
-        let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
-
-        let vertices = &[
-            ModelVertex {
-                position: [0., 0., 0.],
-                color: [1., 0., 0.],
-            },
-            ModelVertex {
-                position: [1., 0., 0.],
-                color: [0., 1., 0.],
-            },
-            ModelVertex {
-                position: [1., -1., 0.],
-                color: [0., 0., 1.],
-            },
-            ModelVertex {
-                position: [0., -1., 0.],
-                color: [0., 1., 0.],
-            },
-        ];
-
-        let indices = &[0, 1, 2, 2, 3, 0];
-
-        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(indices),
-            usage: BufferUsages::INDEX,
-        });
-
-        let mesh = Mesh {
-            name: "Mesh".into(),
-            vertex_buffer,
-            index_buffer,
-            num_indices: 6,
-        };
-
-        let model = Model { meshes: vec![mesh] };
-
-        let model_controller = ModelController::new(1.);
+        let (camera, camera_controller) = player_camera(&world, *window_size, resolution);
 
-        let instance = Instance {
-            position: Vec2::new(0., 0.),
-            rotation: 0.,
-            scale: 100.,
+        let camera_effects = CameraEffects {
+            regions: CameraRegions::new(CameraRegion::from_level(&world.level)),
+            zoom: ZoomTween::new(),
+            shake: ScreenShake::new(Vec2::new(6., 4.), 0.05),
+            bounds: Some(world.level.map.bounds()),
         };
 
-        let instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&[instance.to_raw()]),
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-        });
-
         Self {
             start_time,
             last_update,
+            timestep: FixedTimestep::default(),
             camera,
             camera_controller,
-            camera_uniform,
-            camera_buffer,
-            camera_bind_group,
-            camera_bind_group_layout,
-            model,
-            model_controller,
-            instance,
-            instance_buffer,
-            pressed_keys: HashSet::new(),
+            camera_effects,
+            view_camera: camera,
+            world,
+            keyboard: Keyboard::default(),
+            gamepads: Gamepads::default(),
+            gamepad_source: default_gamepad_source(),
+            bindings: Bindings::load_or_default(Path::new(BINDINGS_PATH)),
+            recorder: None,
         }
     }
 
+    pub fn resize(&mut self, window_size: PhysicalSize<u32>) {
+        self.camera.window_size = window_size;
+        self.view_camera.window_size = window_size;
+    }
+
+    /// Advances the simulation by however many fixed ticks have accumulated
+    /// since the last call.
     pub fn update(&mut self) {
-        // self.camera_controller.set_direction(&self.pressed_keys);
-        // self.camera_controller.update_camera(&mut self.camera);
+        let now = Instant::now();
+        let elapsed = now - self.last_update;
+        self.last_update = now;
+
+        let dt = self.timestep.dt();
+        for _ in 0..self.timestep.advance(elapsed) {
+            self.tick(dt);
+        }
+
+        // The camera is purely visual, so it follows what's drawn and moves
+        // every frame rather than every tick
+        let target = self.interpolated_instance().bounds().center();
+        self.camera_controller.update_camera(
+            &mut self.camera,
+            target,
+            self.world.body.velocity,
+            elapsed.as_secs_f32(),
+        );
+
+        self.camera_effects.regions.target = target;
+        let zoom = &mut self.camera_effects.zoom;
+        if zoom.is_finished() && zoom.zoom() != 1. {
+            zoom.zoom_to(1., 0.6, Easing::SineInOut);
+        }
+        self.view_camera = self
+            .camera_effects
+            .apply(&self.camera, elapsed.as_secs_f32());
+    }
+
+    /// Gathers this tick's input and steps the world by `dt` seconds.
+    pub fn tick(&mut self, dt: f32) {
+        if let Some(source) = &mut self.gamepad_source {
+            self.gamepads.poll(source.as_mut());
+        }
+
+        let frame = self
+            .keyboard
+            .take_frame(&self.bindings)
+            .merge(self.gamepads.take_frame(&self.bindings));
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(frame);
+        }
+
+        self.world.step(frame, dt);
+
+        for event in &self.world.events {
+            match event {
+                WorldEvent::HardLanding => self.camera_effects.shake.add_trauma(0.4),
+                WorldEvent::Crushed | WorldEvent::Hurt => {
+                    self.camera_effects.shake.add_trauma(0.8);
+                    self.camera_effects
+                        .zoom
+                        .zoom_to(RESPAWN_ZOOM, 0.15, Easing::QuadOut);
+                }
+                WorldEvent::Animation(event) => {
+                    tracing::debug!(event, "player animation event")
+                }
+            }
+        }
+    }
 
-        self.model_controller.set_direction(&self.pressed_keys);
-        self.model_controller.update_instance(&mut self.instance);
+    /// The player instance blended between the last two ticks, for rendering.
+    pub fn interpolated_instance(&self) -> Instance {
+        self.world
+            .instance
+            .lerp(&self.world.previous_instance, self.timestep.alpha())
     }
 }
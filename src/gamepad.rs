@@ -0,0 +1,507 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::input::{Action, ActionSet, Axis, Bindings, InputFrame};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GamepadId(pub usize);
+
+/// A gamepad button, named after where it is on the pad rather than what's
+/// printed on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftShoulder,
+    RightShoulder,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// An analog input. Sticks go from -1 to 1, with up being negative like the
+/// world, and triggers from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    /// The other axis of the same stick, if this is a stick axis.
+    fn stick_partner(self) -> Option<Self> {
+        match self {
+            GamepadAxis::LeftStickX => Some(GamepadAxis::LeftStickY),
+            GamepadAxis::LeftStickY => Some(GamepadAxis::LeftStickX),
+            GamepadAxis::RightStickX => Some(GamepadAxis::RightStickY),
+            GamepadAxis::RightStickY => Some(GamepadAxis::RightStickX),
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => None,
+        }
+    }
+
+    fn is_x(self) -> bool {
+        matches!(self, GamepadAxis::LeftStickX | GamepadAxis::RightStickX)
+    }
+}
+
+/// Something on a gamepad that can be bound to an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadInput {
+    Button(GamepadButton),
+    /// An axis pushed past the dead zone towards -1.
+    AxisNegative(GamepadAxis),
+    /// An axis pushed past the dead zone towards 1, or a trigger pulled.
+    AxisPositive(GamepadAxis),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId, String),
+    Disconnected(GamepadId),
+    Button(GamepadId, GamepadButton, bool),
+    Axis(GamepadId, GamepadAxis, f32),
+}
+
+/// Where gamepad events come from, e.g. the operating system or a test.
+pub trait GamepadSource {
+    /// Adds every event since the last poll to `events`.
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>);
+}
+
+/// A gamepad source fed by hand, to drive the game without a real gamepad.
+/// Clones share the same queue, so one can be handed to the game while
+/// another pushes events into it.
+#[derive(Clone, Default)]
+pub struct MockGamepadSource {
+    events: Arc<Mutex<VecDeque<GamepadEvent>>>,
+}
+
+impl MockGamepadSource {
+    pub fn push(&self, event: GamepadEvent) {
+        self.events.lock().unwrap().push_back(event);
+    }
+
+    pub fn connect(&self, id: GamepadId) {
+        self.push(GamepadEvent::Connected(id, "Mock Gamepad".into()));
+    }
+
+    pub fn disconnect(&self, id: GamepadId) {
+        self.push(GamepadEvent::Disconnected(id));
+    }
+
+    pub fn set_button(&self, id: GamepadId, button: GamepadButton, pressed: bool) {
+        self.push(GamepadEvent::Button(id, button, pressed));
+    }
+
+    pub fn set_axis(&self, id: GamepadId, axis: GamepadAxis, value: f32) {
+        self.push(GamepadEvent::Axis(id, axis, value));
+    }
+}
+
+impl GamepadSource for MockGamepadSource {
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        events.extend(self.events.lock().unwrap().drain(..));
+    }
+}
+
+/// Reads real gamepads through gilrs.
+#[cfg(feature = "gilrs")]
+pub struct GilrsSource {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gilrs")]
+impl GilrsSource {
+    pub fn new() -> anyhow::Result<Self> {
+        let gilrs = gilrs::Gilrs::new()
+            .map_err(|e| anyhow::anyhow!("failed to start gamepad support: {}", e))?;
+        Ok(Self { gilrs })
+    }
+}
+
+#[cfg(feature = "gilrs")]
+impl GamepadSource for GilrsSource {
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        use gilrs::{Axis, Button, EventType};
+
+        let button = |button| {
+            Some(match button {
+                Button::South => GamepadButton::South,
+                Button::East => GamepadButton::East,
+                Button::West => GamepadButton::West,
+                Button::North => GamepadButton::North,
+                Button::LeftTrigger => GamepadButton::LeftShoulder,
+                Button::RightTrigger => GamepadButton::RightShoulder,
+                Button::Select => GamepadButton::Select,
+                Button::Start => GamepadButton::Start,
+                Button::LeftThumb => GamepadButton::LeftStick,
+                Button::RightThumb => GamepadButton::RightStick,
+                Button::DPadUp => GamepadButton::DPadUp,
+                Button::DPadDown => GamepadButton::DPadDown,
+                Button::DPadLeft => GamepadButton::DPadLeft,
+                Button::DPadRight => GamepadButton::DPadRight,
+                _ => return None,
+            })
+        };
+
+        while let Some(gilrs::Event {
+            id: gilrs_id,
+            event,
+            ..
+        }) = self.gilrs.next_event()
+        {
+            let id = GamepadId(gilrs_id.into());
+
+            events.extend(match event {
+                EventType::Connected => Some(GamepadEvent::Connected(
+                    id,
+                    self.gilrs.gamepad(gilrs_id).name().into(),
+                )),
+                EventType::Disconnected => Some(GamepadEvent::Disconnected(id)),
+                EventType::ButtonPressed(b, _) => {
+                    button(b).map(|b| GamepadEvent::Button(id, b, true))
+                }
+                EventType::ButtonReleased(b, _) => {
+                    button(b).map(|b| GamepadEvent::Button(id, b, false))
+                }
+                // gilrs reports analog triggers as buttons
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) => {
+                    Some(GamepadEvent::Axis(id, GamepadAxis::LeftTrigger, value))
+                }
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) => {
+                    Some(GamepadEvent::Axis(id, GamepadAxis::RightTrigger, value))
+                }
+                // gilrs has up as positive, the game has it negative
+                EventType::AxisChanged(axis, value, _) => match axis {
+                    Axis::LeftStickX => Some((GamepadAxis::LeftStickX, value)),
+                    Axis::LeftStickY => Some((GamepadAxis::LeftStickY, -value)),
+                    Axis::RightStickX => Some((GamepadAxis::RightStickX, value)),
+                    Axis::RightStickY => Some((GamepadAxis::RightStickY, -value)),
+                    _ => None,
+                }
+                .map(|(axis, value)| GamepadEvent::Axis(id, axis, value)),
+                _ => None,
+            });
+        }
+    }
+}
+
+/// The state of one connected gamepad.
+#[derive(Default)]
+pub struct Gamepad {
+    pub name: String,
+    buttons: HashSet<GamepadButton>,
+    /// Buttons pressed since the last tick, so quick taps aren't missed.
+    pressed: HashSet<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+}
+
+impl Gamepad {
+    pub fn is_held(&self, button: GamepadButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    /// The raw value of `axis`, before any dead zone.
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or_default()
+    }
+
+    /// The value of `axis` with the dead zone applied. Sticks use a radial
+    /// dead zone and are rescaled so they still reach full tilt smoothly
+    /// just past its edge.
+    pub fn axis_with_dead_zone(&self, axis: GamepadAxis, dead_zone: f32) -> f32 {
+        let rescale = |value: f32| ((value - dead_zone) / (1. - dead_zone)).clamp(0., 1.);
+
+        let Some(partner) = axis.stick_partner() else {
+            return rescale(self.axis(axis).abs()) * self.axis(axis).signum();
+        };
+
+        let stick = if axis.is_x() {
+            Vec2::new(self.axis(axis), self.axis(partner))
+        } else {
+            Vec2::new(self.axis(partner), self.axis(axis))
+        };
+        let length = stick.length();
+        if length <= dead_zone {
+            return 0.;
+        }
+
+        let value = if axis.is_x() { stick.x } else { stick.y };
+        value / length * rescale(length.min(1.))
+    }
+
+    /// How far `input` is pushed, from 0 to 1.
+    fn value(&self, input: GamepadInput, bindings: &Bindings) -> f32 {
+        match input {
+            GamepadInput::Button(button) => {
+                let held = self.buttons.contains(&button) || self.pressed.contains(&button);
+                if held {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            GamepadInput::AxisNegative(axis) => {
+                (-self.axis_with_dead_zone(axis, bindings.dead_zone)).max(0.)
+            }
+            GamepadInput::AxisPositive(axis) => {
+                self.axis_with_dead_zone(axis, bindings.dead_zone).max(0.)
+            }
+        }
+    }
+}
+
+/// Every connected gamepad.
+#[derive(Default)]
+pub struct Gamepads {
+    pub gamepads: HashMap<GamepadId, Gamepad>,
+}
+
+impl Gamepads {
+    pub fn handle_event(&mut self, event: GamepadEvent) {
+        match event {
+            GamepadEvent::Connected(id, name) => {
+                tracing::info!(?id, name, "gamepad connected");
+                self.gamepads.insert(
+                    id,
+                    Gamepad {
+                        name,
+                        ..Default::default()
+                    },
+                );
+            }
+            GamepadEvent::Disconnected(id) => {
+                tracing::info!(?id, "gamepad disconnected");
+                // Forgetting the gamepad releases everything held on it
+                self.gamepads.remove(&id);
+            }
+            GamepadEvent::Button(id, button, pressed) => {
+                let Some(gamepad) = self.gamepads.get_mut(&id) else {
+                    return;
+                };
+
+                if pressed {
+                    gamepad.buttons.insert(button);
+                    gamepad.pressed.insert(button);
+                } else {
+                    gamepad.buttons.remove(&button);
+                }
+            }
+            GamepadEvent::Axis(id, axis, value) => {
+                if let Some(gamepad) = self.gamepads.get_mut(&id) {
+                    gamepad.axes.insert(axis, value.clamp(-1., 1.));
+                }
+            }
+        }
+    }
+
+    /// Polls `source` and handles every event it had.
+    pub fn poll(&mut self, source: &mut dyn GamepadSource) {
+        let mut events = Vec::new();
+        source.poll(&mut events);
+        for event in events {
+            self.handle_event(event);
+        }
+    }
+
+    /// How far `action` is pushed on any gamepad, from 0 to 1.
+    pub fn action_value(&self, action: Action, bindings: &Bindings) -> f32 {
+        let inputs = bindings.gamepad_inputs_for(action);
+
+        self.gamepads
+            .values()
+            .flat_map(|gamepad| {
+                inputs
+                    .iter()
+                    .map(move |input| gamepad.value(*input, bindings))
+            })
+            .fold(0., f32::max)
+    }
+
+    /// The input for the coming tick from every gamepad. Analog inputs hold
+    /// their actions once pushed past half way.
+    pub fn take_frame(&mut self, bindings: &Bindings) -> InputFrame {
+        let values: Vec<f32> = Action::ALL
+            .iter()
+            .map(|action| self.action_value(*action, bindings))
+            .collect();
+
+        let held: ActionSet = Action::ALL
+            .into_iter()
+            .filter(|action| values[*action as usize] >= bindings.press_threshold)
+            .collect();
+
+        let mut frame = InputFrame {
+            held,
+            ..Default::default()
+        };
+        for axis in Axis::ALL {
+            let (negative, positive) = axis.actions();
+            frame.set_axis(axis, values[positive as usize] - values[negative as usize]);
+        }
+
+        for gamepad in self.gamepads.values_mut() {
+            gamepad.pressed.clear();
+        }
+
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::{ElementState, VirtualKeyCode};
+
+    use super::*;
+    use crate::input::Keyboard;
+
+    const PAD: GamepadId = GamepadId(0);
+
+    /// Gamepads fed by a mock, with a connected pad, and a handle to script
+    /// it with.
+    fn connected() -> (Gamepads, MockGamepadSource) {
+        let mock = MockGamepadSource::default();
+        let mut gamepads = Gamepads::default();
+        mock.connect(PAD);
+        gamepads.poll(&mut mock.clone());
+        (gamepads, mock)
+    }
+
+    fn stick(gamepads: &mut Gamepads, mock: &MockGamepadSource, x: f32, y: f32) -> Vec2 {
+        mock.set_axis(PAD, GamepadAxis::LeftStickX, x);
+        mock.set_axis(PAD, GamepadAxis::LeftStickY, y);
+        gamepads.poll(&mut mock.clone());
+
+        let gamepad = &gamepads.gamepads[&PAD];
+        Vec2::new(
+            gamepad.axis_with_dead_zone(GamepadAxis::LeftStickX, 0.2),
+            gamepad.axis_with_dead_zone(GamepadAxis::LeftStickY, 0.2),
+        )
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn stick_dead_zone_is_radial_and_rescaled() {
+        let (mut gamepads, mock) = connected();
+
+        assert_eq!(stick(&mut gamepads, &mock, 0.15, 0.), Vec2::ZERO);
+        assert_near(stick(&mut gamepads, &mock, 0.6, 0.), Vec2::new(0.5, 0.));
+        assert_near(stick(&mut gamepads, &mock, 0., -1.), Vec2::new(0., -1.));
+        // Full tilt on a diagonal keeps its direction
+        assert_near(stick(&mut gamepads, &mock, 0.6, 0.8), Vec2::new(0.6, 0.8));
+
+        // Each axis is inside the dead zone on its own, but the stick isn't
+        let diagonal = stick(&mut gamepads, &mock, 0.18, 0.18);
+        assert!(diagonal.x > 0. && diagonal.x == diagonal.y, "{}", diagonal);
+    }
+
+    #[test]
+    fn analog_inputs_hold_actions_past_the_threshold() {
+        let (mut gamepads, mock) = connected();
+        let bindings = Bindings::default();
+
+        // 0.375 after the dead zone, short of the 0.5 threshold
+        mock.set_axis(PAD, GamepadAxis::RightTrigger, 0.5);
+        mock.set_axis(PAD, GamepadAxis::LeftStickX, 0.5);
+        gamepads.poll(&mut mock.clone());
+        let frame = gamepads.take_frame(&bindings);
+        assert!(frame.held.is_empty());
+        assert!((frame.axis(Axis::MoveX) - 0.375).abs() < 0.01);
+
+        mock.set_axis(PAD, GamepadAxis::RightTrigger, 0.7);
+        mock.set_axis(PAD, GamepadAxis::LeftStickX, 0.7);
+        gamepads.poll(&mut mock.clone());
+        let frame = gamepads.take_frame(&bindings);
+        assert_eq!(
+            frame.held,
+            ActionSet::from_iter([Action::MoveRight, Action::Dash])
+        );
+    }
+
+    #[test]
+    fn gamepads_come_and_go() {
+        let mock = MockGamepadSource::default();
+        let mut gamepads = Gamepads::default();
+        let bindings = Bindings::default();
+
+        // Events from a gamepad that isn't connected are ignored
+        mock.set_button(PAD, GamepadButton::South, true);
+        gamepads.poll(&mut mock.clone());
+        assert!(gamepads.take_frame(&bindings).held.is_empty());
+
+        mock.connect(PAD);
+        mock.set_button(PAD, GamepadButton::South, true);
+        gamepads.poll(&mut mock.clone());
+        assert_eq!(gamepads.gamepads[&PAD].name, "Mock Gamepad");
+        assert!(gamepads.take_frame(&bindings).held.contains(Action::Jump));
+
+        // Unplugging releases everything held on it
+        mock.disconnect(PAD);
+        gamepads.poll(&mut mock.clone());
+        assert!(gamepads.gamepads.is_empty());
+        assert!(gamepads.take_frame(&bindings).held.is_empty());
+
+        mock.connect(PAD);
+        gamepads.poll(&mut mock.clone());
+        assert!(!gamepads.gamepads[&PAD].is_held(GamepadButton::South));
+    }
+
+    #[test]
+    fn quick_button_taps_last_a_tick() {
+        let (mut gamepads, mock) = connected();
+        let bindings = Bindings::default();
+
+        mock.set_button(PAD, GamepadButton::South, true);
+        mock.set_button(PAD, GamepadButton::South, false);
+        gamepads.poll(&mut mock.clone());
+
+        assert!(gamepads.take_frame(&bindings).held.contains(Action::Jump));
+        assert!(gamepads.take_frame(&bindings).held.is_empty());
+    }
+
+    #[test]
+    fn gamepad_input_merges_with_the_keyboard() {
+        let (mut gamepads, mock) = connected();
+        let bindings = Bindings::default();
+        let mut keyboard = Keyboard::default();
+
+        keyboard.handle_key(VirtualKeyCode::A, ElementState::Pressed);
+        mock.set_axis(PAD, GamepadAxis::LeftStickX, 0.5);
+        mock.set_button(PAD, GamepadButton::DPadUp, true);
+        mock.set_button(PAD, GamepadButton::South, true);
+        gamepads.poll(&mut mock.clone());
+
+        let frame = keyboard
+            .take_frame(&bindings)
+            .merge(gamepads.take_frame(&bindings));
+        assert_eq!(
+            frame.held,
+            ActionSet::from_iter([Action::MoveLeft, Action::MoveUp, Action::Jump])
+        );
+        // Each axis goes with whichever pushes it further
+        assert_eq!(frame.axis(Axis::MoveX), -1.);
+        assert_eq!(frame.axis(Axis::MoveY), -1.);
+    }
+}
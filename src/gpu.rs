@@ -0,0 +1,151 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Context, Result};
+use wgpu::{
+    Adapter, Backends, Device, DeviceDescriptor, Features, Instance, InstanceDescriptor, Limits,
+    PowerPreference, Queue, RequestAdapterOptions, Surface,
+};
+use winit::window::Window;
+
+/// One way of asking for an adapter. They're tried in order until one of them
+/// finds something.
+#[derive(Clone, Copy, Debug)]
+pub struct AdapterAttempt {
+    pub backends: Backends,
+    /// Only accept a software adapter, e.g. llvmpipe or WARP.
+    pub force_fallback_adapter: bool,
+}
+
+impl AdapterAttempt {
+    /// The modern APIs first, then the older ones, then anything at all
+    /// running on the CPU.
+    pub const DEFAULT: [AdapterAttempt; 3] = [
+        AdapterAttempt {
+            backends: Backends::PRIMARY,
+            force_fallback_adapter: false,
+        },
+        AdapterAttempt {
+            backends: Backends::SECONDARY,
+            force_fallback_adapter: false,
+        },
+        AdapterAttempt {
+            backends: Backends::all(),
+            force_fallback_adapter: true,
+        },
+    ];
+
+    fn describe(&self) -> String {
+        let kind = if self.force_fallback_adapter {
+            "software adapter"
+        } else {
+            "adapter"
+        };
+        format!("{} on {:?}", kind, self.backends)
+    }
+}
+
+/// The adapter everything is drawn with, along with the instance it came
+/// from and, when drawing to a window, the window's surface.
+pub struct Gpu {
+    pub instance: Instance,
+    pub surface: Option<Surface>,
+    pub adapter: Adapter,
+}
+
+impl Gpu {
+    /// Finds an adapter, trying each of `attempts` in turn. With a `window`,
+    /// only adapters that can draw to it count.
+    ///
+    /// # Safety
+    ///
+    /// The surface has to be dropped before `window` is.
+    pub async unsafe fn new(
+        window: Option<&Window>,
+        attempts: &[AdapterAttempt],
+        power_preference: PowerPreference,
+    ) -> Result<Self> {
+        let mut failures = String::new();
+        for attempt in attempts {
+            let instance = Instance::new(InstanceDescriptor {
+                backends: attempt.backends,
+                dx12_shader_compiler: Default::default(),
+            });
+
+            let surface = match window.map(|window| instance.create_surface(window)) {
+                Some(Ok(surface)) => Some(surface),
+                Some(Err(e)) => {
+                    let _ = writeln!(failures, "  {}: {}", attempt.describe(), e);
+                    continue;
+                }
+                None => None,
+            };
+
+            let adapter = instance
+                .request_adapter(&RequestAdapterOptions {
+                    power_preference,
+                    compatible_surface: surface.as_ref(),
+                    force_fallback_adapter: attempt.force_fallback_adapter,
+                })
+                .await;
+            let Some(adapter) = adapter else {
+                let _ = writeln!(failures, "  {}: nothing suitable", attempt.describe());
+                continue;
+            };
+
+            let info = adapter.get_info();
+            tracing::info!(
+                name = %info.name,
+                backend = ?info.backend,
+                device_type = ?info.device_type,
+                driver = %info.driver,
+                "using GPU adapter"
+            );
+
+            return Ok(Self {
+                instance,
+                surface,
+                adapter,
+            });
+        }
+
+        bail!(
+            "couldn't find a GPU adapter to draw with. Tried:\n{}\
+             Updating your graphics drivers may help.",
+            failures
+        )
+    }
+
+    /// Opens the adapter. Falls back to lower limits if it can't meet the
+    /// defaults, as software and older adapters often can't.
+    pub async fn request_device(&self) -> Result<(Device, Queue)> {
+        let descriptor = |limits: Limits| DeviceDescriptor {
+            features: Features::empty(),
+            limits: limits.using_resolution(self.adapter.limits()),
+            label: None,
+        };
+
+        // WebGL doesn't support all of wgpu's features, so if we're building
+        // for the web we'll have to disable some.
+        let limits = if cfg!(target_arch = "wasm32") {
+            Limits::downlevel_webgl2_defaults()
+        } else {
+            Limits::default()
+        };
+
+        match self.adapter.request_device(&descriptor(limits), None).await {
+            Ok(device) => Ok(device),
+            Err(e) => {
+                tracing::warn!("{}, retrying with lower limits", e);
+                self.adapter
+                    .request_device(&descriptor(Limits::downlevel_webgl2_defaults()), None)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "couldn't open the GPU adapter {}",
+                            self.adapter.get_info().name
+                        )
+                    })
+            }
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use winit::event::{ElementState, VirtualKeyCode};
+
+use crate::{
+    config::ConfigFile,
+    gamepad::{GamepadAxis, GamepadButton, GamepadInput},
+};
+
+/// Something the player can do, independent of which keys or buttons do it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    Dash,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Jump,
+        Action::Dash,
+        Action::Pause,
+    ];
+}
+
+/// A value from -1 to 1 made out of a pair of opposing actions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    /// Left is negative, right positive.
+    MoveX,
+    /// Up is negative, down positive, like the world.
+    MoveY,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 2] = [Axis::MoveX, Axis::MoveY];
+
+    /// The actions pushing the axis towards -1 and 1.
+    pub fn actions(self) -> (Action, Action) {
+        match self {
+            Axis::MoveX => (Action::MoveLeft, Action::MoveRight),
+            Axis::MoveY => (Action::MoveUp, Action::MoveDown),
+        }
+    }
+}
+
+/// A set of actions, e.g. the ones held during a tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ActionSet(u16);
+
+impl ActionSet {
+    pub const EMPTY: Self = Self(0);
+
+    pub fn contains(self, action: Action) -> bool {
+        self.0 & Self::bit(action) != 0
+    }
+
+    pub fn insert(&mut self, action: Action) {
+        self.0 |= Self::bit(action);
+    }
+
+    pub fn remove(&mut self, action: Action) {
+        self.0 &= !Self::bit(action);
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    fn bit(action: Action) -> u16 {
+        1 << action as u16
+    }
+}
+
+impl FromIterator<Action> for ActionSet {
+    fn from_iter<T: IntoIterator<Item = Action>>(iter: T) -> Self {
+        let mut set = Self::EMPTY;
+        for action in iter {
+            set.insert(action);
+        }
+        set
+    }
+}
+
+/// Everything the simulation needs to know about the input for one tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct InputFrame {
+    pub held: ActionSet,
+    /// The value of each `Axis`, from -127 to 127. Storing them as whole
+    /// numbers keeps recordings small and lets replays feed the simulation
+    /// exactly the values it saw.
+    pub axes: [i8; Axis::ALL.len()],
+}
+
+impl InputFrame {
+    /// The value of `axis`, from -1 to 1.
+    pub fn axis(&self, axis: Axis) -> f32 {
+        self.axes[axis as usize] as f32 / i8::MAX as f32
+    }
+
+    /// Sets `axis` to `value`, from -1 to 1.
+    pub fn set_axis(&mut self, axis: Axis, value: f32) {
+        self.axes[axis as usize] = (value.clamp(-1., 1.) * i8::MAX as f32).round() as i8;
+    }
+
+    /// Combines the input from two devices. Actions held on either are
+    /// held, and each axis goes with whichever device pushes it further.
+    pub fn merge(mut self, other: InputFrame) -> InputFrame {
+        self.held.0 |= other.held.0;
+
+        for (axis, other) in self.axes.iter_mut().zip(other.axes) {
+            if other.unsigned_abs() > axis.unsigned_abs() {
+                *axis = other;
+            }
+        }
+
+        self
+    }
+}
+
+/// Which keys and gamepad inputs trigger which actions. Any number of them
+/// can be bound to an action, and each can trigger more than one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    /// How far sticks and triggers have to move before they count at all.
+    pub dead_zone: f32,
+    /// How far sticks and triggers have to move to hold their actions.
+    pub press_threshold: f32,
+    pub keys: BTreeMap<Action, Vec<VirtualKeyCode>>,
+    pub gamepad: BTreeMap<Action, Vec<GamepadInput>>,
+}
+
+impl ConfigFile for Bindings {
+    const DESCRIPTION: &'static str = "bindings";
+}
+
+impl Bindings {
+    pub fn keys_for(&self, action: Action) -> &[VirtualKeyCode] {
+        self.keys.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn gamepad_inputs_for(&self, action: Action) -> &[GamepadInput] {
+        self.gamepad.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces every key bound to `action` with `key`.
+    pub fn rebind(&mut self, action: Action, key: VirtualKeyCode) {
+        self.keys.insert(action, vec![key]);
+    }
+
+    /// Binds `key` to `action` as well as the keys already bound to it.
+    pub fn bind(&mut self, action: Action, key: VirtualKeyCode) {
+        let keys = self.keys.entry(action).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    /// The actions `keys` trigger.
+    pub fn actions<'a>(&self, keys: impl IntoIterator<Item = &'a VirtualKeyCode>) -> ActionSet {
+        let keys: Vec<_> = keys.into_iter().collect();
+        self.keys
+            .iter()
+            .filter(|(_, bound)| bound.iter().any(|key| keys.contains(&key)))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use GamepadAxis::*;
+        use GamepadButton::*;
+        use GamepadInput::*;
+        use VirtualKeyCode::*;
+
+        Self {
+            dead_zone: 0.2,
+            press_threshold: 0.5,
+            keys: BTreeMap::from([
+                (Action::MoveLeft, vec![A, Left]),
+                (Action::MoveRight, vec![D, Right]),
+                (Action::MoveUp, vec![W, Up]),
+                (Action::MoveDown, vec![S, Down]),
+                (Action::Jump, vec![Space, Z]),
+                (Action::Dash, vec![LShift, X]),
+                (Action::Pause, vec![P, Return]),
+            ]),
+            gamepad: BTreeMap::from([
+                (
+                    Action::MoveLeft,
+                    vec![AxisNegative(LeftStickX), Button(DPadLeft)],
+                ),
+                (
+                    Action::MoveRight,
+                    vec![AxisPositive(LeftStickX), Button(DPadRight)],
+                ),
+                (
+                    Action::MoveUp,
+                    vec![AxisNegative(LeftStickY), Button(DPadUp)],
+                ),
+                (
+                    Action::MoveDown,
+                    vec![AxisPositive(LeftStickY), Button(DPadDown)],
+                ),
+                (Action::Jump, vec![Button(South)]),
+                (Action::Dash, vec![Button(West), AxisPositive(RightTrigger)]),
+                (Action::Pause, vec![Button(Start)]),
+            ]),
+        }
+    }
+}
+
+/// Keeps track of the keyboard between ticks.
+#[derive(Default)]
+pub struct Keyboard {
+    held: HashSet<VirtualKeyCode>,
+    /// Keys pressed since the last tick. Keeps taps shorter than a tick from
+    /// going unnoticed.
+    pressed: HashSet<VirtualKeyCode>,
+}
+
+impl Keyboard {
+    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                self.held.insert(key);
+                self.pressed.insert(key);
+            }
+            ElementState::Released => {
+                self.held.remove(&key);
+            }
+        }
+    }
+
+    pub fn is_held(&self, key: VirtualKeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// The input for the coming tick, including actions whose keys were
+    /// pressed and released since the last.
+    pub fn take_frame(&mut self, bindings: &Bindings) -> InputFrame {
+        let held = bindings.actions(self.held.union(&self.pressed));
+        self.pressed.clear();
+
+        let mut frame = InputFrame {
+            held,
+            ..Default::default()
+        };
+        for axis in Axis::ALL {
+            let (negative, positive) = axis.actions();
+            let value = |action| if held.contains(action) { 1. } else { 0. };
+            frame.set_axis(axis, value(positive) - value(negative));
+        }
+
+        frame
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ActionState {
+    pub held: bool,
+    pub just_pressed: bool,
+    pub just_released: bool,
+    /// How long the action has been held, in seconds.
+    pub held_for: f32,
+}
+
+/// The state of every action, updated once per simulation tick so queries
+/// like `just_pressed` mean the same thing no matter the frame rate.
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    actions: [ActionState; Action::ALL.len()],
+    frame: InputFrame,
+}
+
+impl InputState {
+    /// Moves on to the next tick, `dt` seconds after the last, with the
+    /// input in `frame`.
+    pub fn update(&mut self, frame: InputFrame, dt: f32) {
+        for action in Action::ALL {
+            let state = &mut self.actions[action as usize];
+            let was_held = state.held;
+
+            state.held = frame.held.contains(action);
+            state.just_pressed = state.held && !was_held;
+            state.just_released = !state.held && was_held;
+            state.held_for = match (state.held, was_held) {
+                (true, true) => state.held_for + dt,
+                _ => 0.,
+            };
+        }
+
+        self.frame = frame;
+    }
+
+    pub fn action(&self, action: Action) -> ActionState {
+        self.actions[action as usize]
+    }
+
+    /// This tick's input.
+    pub fn frame(&self) -> InputFrame {
+        self.frame
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.action(action).held
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.action(action).just_pressed
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.action(action).just_released
+    }
+
+    /// How long `action` has been held, in seconds, or zero if it isn't.
+    pub fn held_duration(&self, action: Action) -> f32 {
+        self.action(action).held_for
+    }
+
+    pub fn axis(&self, axis: Axis) -> f32 {
+        self.frame.axis(axis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.25;
+
+    fn frame(held: &[Action]) -> InputFrame {
+        InputFrame {
+            held: held.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn tap(keyboard: &mut Keyboard, key: VirtualKeyCode) {
+        keyboard.handle_key(key, ElementState::Pressed);
+        keyboard.handle_key(key, ElementState::Released);
+    }
+
+    #[test]
+    fn presses_and_releases_last_one_tick() {
+        let mut input = InputState::default();
+        let dash = |input: &InputState| {
+            (
+                input.pressed(Action::Dash),
+                input.just_pressed(Action::Dash),
+                input.just_released(Action::Dash),
+                input.held_duration(Action::Dash),
+            )
+        };
+
+        input.update(frame(&[Action::Dash]), DT);
+        assert_eq!(dash(&input), (true, true, false, 0.));
+        input.update(frame(&[Action::Dash]), DT);
+        assert_eq!(dash(&input), (true, false, false, 0.25));
+        input.update(frame(&[Action::Dash, Action::Jump]), DT);
+        assert_eq!(dash(&input), (true, false, false, 0.5));
+
+        input.update(frame(&[]), DT);
+        assert_eq!(dash(&input), (false, false, true, 0.));
+        input.update(frame(&[]), DT);
+        assert_eq!(dash(&input), (false, false, false, 0.));
+
+        // Pressing again starts the count over
+        input.update(frame(&[Action::Dash]), DT);
+        assert_eq!(dash(&input), (true, true, false, 0.));
+    }
+
+    #[test]
+    fn taps_inside_one_frame_still_last_a_tick() {
+        let bindings = Bindings::default();
+        let mut keyboard = Keyboard::default();
+        let mut input = InputState::default();
+
+        tap(&mut keyboard, VirtualKeyCode::Space);
+        assert!(!keyboard.is_held(VirtualKeyCode::Space));
+        input.update(keyboard.take_frame(&bindings), DT);
+        assert!(input.just_pressed(Action::Jump));
+
+        input.update(keyboard.take_frame(&bindings), DT);
+        assert!(input.just_released(Action::Jump));
+        assert!(!input.pressed(Action::Jump));
+    }
+
+    #[test]
+    fn default_bindings_keep_jump_off_up() {
+        let bindings = Bindings::default();
+        let mut keyboard = Keyboard::default();
+
+        keyboard.handle_key(VirtualKeyCode::Up, ElementState::Pressed);
+        let up = keyboard.take_frame(&bindings);
+        assert_eq!(up.held, ActionSet::from_iter([Action::MoveUp]));
+        assert_eq!(up.axis(Axis::MoveY), -1.);
+
+        keyboard.handle_key(VirtualKeyCode::Up, ElementState::Released);
+        keyboard.handle_key(VirtualKeyCode::Space, ElementState::Pressed);
+        let jump = keyboard.take_frame(&bindings);
+        assert_eq!(jump.held, ActionSet::from_iter([Action::Jump]));
+        assert_eq!(jump.axis(Axis::MoveY), 0.);
+    }
+
+    #[test]
+    fn rebinding_replaces_the_keys() {
+        let mut bindings = Bindings::default();
+        let jumps = |bindings: &Bindings, key| bindings.actions([&key]).contains(Action::Jump);
+
+        bindings.rebind(Action::Jump, VirtualKeyCode::K);
+        assert!(jumps(&bindings, VirtualKeyCode::K));
+        assert!(!jumps(&bindings, VirtualKeyCode::Space));
+
+        // Binding adds to the keys instead
+        bindings.bind(Action::Jump, VirtualKeyCode::Space);
+        bindings.bind(Action::Jump, VirtualKeyCode::Space);
+        assert_eq!(
+            bindings.keys_for(Action::Jump),
+            [VirtualKeyCode::K, VirtualKeyCode::Space]
+        );
+    }
+}
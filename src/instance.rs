@@ -1,14 +1,43 @@
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Quat, Vec2, Vec3};
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
 
+use crate::collision::Aabb;
+
+#[derive(Clone, Copy)]
 pub struct Instance {
     pub position: Vec2,
     pub rotation: f32,
     pub scale: f32,
+    /// Multiplied with the sprite's texture, the alpha channel fades the
+    /// whole sprite.
+    pub tint: Vec4,
+    /// The part of the texture to draw: the top-left corner in `x` and `y`,
+    /// followed by the width and height, all in texture coordinates.
+    pub uv_rect: Vec4,
 }
 
 impl Instance {
+    /// The whole texture.
+    pub const FULL_UV_RECT: Vec4 = Vec4::new(0., 0., 1., 1.);
+
+    /// Blends between `previous` and `self`, where an `alpha` of 0 gives
+    /// `previous` and 1 gives `self`.
+    pub fn lerp(&self, previous: &Instance, alpha: f32) -> Instance {
+        Instance {
+            position: previous.position.lerp(self.position, alpha),
+            rotation: previous.rotation + (self.rotation - previous.rotation) * alpha,
+            scale: previous.scale + (self.scale - previous.scale) * alpha,
+            tint: previous.tint.lerp(self.tint, alpha),
+            uv_rect: self.uv_rect,
+        }
+    }
+
+    /// The area the sprite covers, ignoring rotation.
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_position_size(self.position, Vec2::splat(self.scale))
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
         InstanceRaw {
             model: Mat4::from_scale_rotation_translation(
@@ -16,6 +45,8 @@ impl Instance {
                 Quat::from_rotation_z(self.rotation),
                 Vec3::new(self.position.x, self.position.y, 0.),
             ),
+            tint: self.tint,
+            uv_rect: self.uv_rect,
         }
     }
 }
@@ -24,6 +55,8 @@ impl Instance {
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct InstanceRaw {
     pub model: Mat4,
+    pub tint: Vec4,
+    pub uv_rect: Vec4,
 }
 
 impl InstanceRaw {
@@ -54,6 +87,16 @@ impl InstanceRaw {
                     shader_location: 5,
                     format: VertexFormat::Float32x4,
                 },
+                VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: VertexFormat::Float32x4,
+                },
+                VertexAttribute {
+                    offset: mem::size_of::<[f32; 20]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: VertexFormat::Float32x4,
+                },
             ],
         }
     }
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec4};
+
+use crate::{
+    collision::{Aabb, Collider, Colliders},
+    tilemap::TileMap,
+};
+
+/// A custom property set on a map, layer or object in the level editor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl PropertyValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PropertyValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            PropertyValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            PropertyValue::Int(value) => Some(*value as f32),
+            PropertyValue::Float(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+/// Anything placed on an object layer: spawn points, triggers, paths and so
+/// on. What an object means is decided by its `class`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelObject {
+    pub id: u32,
+    pub name: String,
+    pub class: String,
+    /// The name of the object layer the object was placed on.
+    pub layer: String,
+    /// The top-left corner for rectangles, or the point itself for point
+    /// objects.
+    pub position: Vec2,
+    pub size: Vec2,
+    /// The points of a polyline object, in world space.
+    pub polyline: Option<Vec<Vec2>>,
+    pub properties: Properties,
+}
+
+impl LevelObject {
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_position_size(self.position, self.size)
+    }
+
+    pub fn property(&self, name: &str) -> Option<&PropertyValue> {
+        self.properties.get(name)
+    }
+}
+
+/// The image a range of tile ids is drawn from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tileset {
+    pub first_gid: u32,
+    pub name: String,
+    /// The path of the image, relative to the level file.
+    pub image: Option<String>,
+    pub columns: u32,
+    pub tile_count: u32,
+}
+
+/// Everything loaded from a level file.
+pub struct Level {
+    pub map: TileMap,
+    pub tilesets: Vec<Tileset>,
+    pub objects: Vec<LevelObject>,
+    pub properties: Properties,
+}
+
+impl Tileset {
+    /// The part of the tileset image showing tile `id`, in the format of
+    /// `Instance::uv_rect`. Assumes the tiles are packed without margins or
+    /// spacing.
+    pub fn uv_rect(&self, id: u32) -> Vec4 {
+        let columns = self.columns.max(1);
+        let rows = self.tile_count.div_ceil(columns).max(1);
+        let local = id - self.first_gid;
+        let size = Vec2::new(1. / columns as f32, 1. / rows as f32);
+
+        Vec4::new(
+            (local % columns) as f32 * size.x,
+            (local / columns) as f32 * size.y,
+            size.x,
+            size.y,
+        )
+    }
+}
+
+impl Level {
+    /// The tileset tile `id` belongs to.
+    pub fn tileset_for(&self, id: u32) -> Option<usize> {
+        self.tilesets
+            .iter()
+            .rposition(|tileset| tileset.first_gid <= id)
+    }
+
+    pub fn objects_of_class<'a>(
+        &'a self,
+        class: &'a str,
+    ) -> impl Iterator<Item = &'a LevelObject> + 'a {
+        self.objects
+            .iter()
+            .filter(move |object| object.class == class)
+    }
+
+    /// Where the player can start, taken from the `spawn` objects.
+    pub fn spawn_points(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.objects_of_class("spawn").map(|object| object.position)
+    }
+
+    pub fn triggers(&self) -> impl Iterator<Item = &LevelObject> {
+        self.objects_of_class("trigger")
+    }
+}
+
+/// The tiles, plus one-way platforms placed as `one_way_platform` objects,
+/// for platforms that don't line up with the grid.
+impl Colliders for Level {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        self.map.colliders_in(region, out);
+        out.extend(
+            self.objects_of_class("one_way_platform")
+                .map(|object| Collider::one_way(object.bounds()))
+                .filter(|collider| collider.bounds.intersects(region)),
+        );
+    }
+}
@@ -1,97 +1,135 @@
-use glam::Vec2;
+use std::path::PathBuf;
+
 use wgpu::{SurfaceError, VertexBufferLayout};
-use winit::dpi::PhysicalSize;
 use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 
-mod camera;
-mod game_state;
-mod instance;
-mod model;
-mod resources;
-mod state;
-mod texture;
+pub mod animation;
+pub mod atlas;
+pub mod body;
+pub mod camera;
+pub mod camera_effects;
+pub mod collision;
+pub mod config;
+pub mod game_state;
+pub mod gamepad;
+pub mod gpu;
+pub mod input;
+pub mod instance;
+pub mod level;
+pub mod model;
+pub mod platform;
+pub mod renderer;
+pub mod replay;
+pub mod resources;
+pub mod screenshot;
+pub mod settings;
+pub mod sprite_batch;
+pub mod state;
+pub mod texture;
+pub mod tiled;
+pub mod tilemap;
+pub mod timestep;
+pub mod tween;
+pub mod virtual_screen;
+pub mod world;
 
-use state::State;
+use input::InputFrame;
+use replay::{Recorder, Replay};
+use settings::Settings;
+use state::{State, LEVEL};
+use timestep::FixedTimestep;
+use world::World;
 
 pub trait Vertex {
     fn desc<'a>() -> VertexBufferLayout<'a>;
 }
 
-pub enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+/// Steps the world `ticks` times with no input, without a window or a GPU,
+/// and returns it.
+pub async fn run_headless(ticks: u64, seed: u64) -> anyhow::Result<World> {
+    let level = resources::load_level(LEVEL).await?;
+    let mut world = World::new(level, seed);
 
-impl Direction {
-    pub fn from_virtual_keycode(keycode: &VirtualKeyCode) -> Option<Self> {
-        match keycode {
-            VirtualKeyCode::W | VirtualKeyCode::Up => Some(Self::Up),
-            VirtualKeyCode::S | VirtualKeyCode::Down => Some(Self::Down),
-            VirtualKeyCode::A | VirtualKeyCode::Left => Some(Self::Left),
-            VirtualKeyCode::D | VirtualKeyCode::Right => Some(Self::Right),
-            _ => None,
-        }
+    let dt = FixedTimestep::default().dt();
+    for _ in 0..ticks {
+        world.step(InputFrame::default(), dt);
     }
 
-    pub fn to_vec2(&self) -> Vec2 {
-        match self {
-            Direction::Up => Vec2::Y,
-            Direction::Down => Vec2::NEG_Y,
-            Direction::Left => Vec2::X,
-            Direction::Right => Vec2::NEG_X,
-        }
-    }
+    Ok(world)
 }
 
-pub async fn run() {
+/// Opens the game in a window set up by `settings`. With `record` set, every
+/// tick's input is saved to that file as a replay when the window closes.
+pub async fn run(settings: Settings, record: Option<PathBuf>) -> anyhow::Result<()> {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("Plat4rs")
-        .with_inner_size(PhysicalSize::new(800, 600))
-        .build(&event_loop)
-        .unwrap();
+        .with_inner_size(settings.window_size())
+        .with_fullscreen(
+            settings
+                .window_mode
+                .fullscreen(event_loop.primary_monitor()),
+        )
+        .build(&event_loop)?;
 
-    let mut state = State::new(window).await;
+    let mut state = State::new(window, settings).await?;
+
+    if let Some(path) = record {
+        let world = &state.game_state.world;
+        let replay = Replay::new(LEVEL, world.seed, state.game_state.timestep.tick_rate);
+        state.game_state.recorder = Some(Recorder::new(path, replay));
+    }
 
     event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent { window_id, event } if window_id == state.window().id() => {
-            if !state.input(&event) {
-                match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(physical_size) => state.resize(physical_size),
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(*new_inner_size)
+        Event::WindowEvent { window_id, event }
+            if window_id == state.window().id() && !state.input(&event) =>
+        {
+            match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } => {
+                    let game_state = &mut state.game_state;
+                    if let Some(recorder) = &mut game_state.recorder {
+                        if let Err(e) = recorder.save(&game_state.world) {
+                            tracing::error!("{:#}", e);
+                        }
                     }
-                    _ => (),
+
+                    *control_flow = ControlFlow::Exit
+                }
+                WindowEvent::Resized(physical_size) => state.resize(physical_size),
+                // The window keeps its logical size, so its physical size
+                // changes with the scale factor
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    state.resize(*new_inner_size)
                 }
+                _ => (),
             }
         }
         Event::RedrawRequested(window_id) if window_id == state.window().id() => {
             state.update();
             match state.render() {
                 Ok(_) => (),
-                // Reconfigure the surface if lost
-                Err(SurfaceError::Lost) => state.resize(state.size),
+                // Reconfigure the surface if lost or out of date
+                Err(SurfaceError::Lost | SurfaceError::Outdated) => state.resize(state.size),
                 // The system is out of memory, we should probably quit
                 Err(SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                // All other errors (Outdated, Timeout) should be resolved by the next frame
+                // All other errors (Timeout) should be resolved by the next frame
                 Err(e) => eprintln!("{:?}", e),
             }
         }
         Event::MainEventsCleared => {
+            // Runs as many fixed-length simulation ticks as the time since
+            // the last frame calls for.
             state.game_state.update();
 
             // RedrawRequested will only trigger once, unless we manually
@@ -1,7 +1,77 @@
-use plat4rs::run;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use plat4rs::{
+    config::ConfigFile,
+    game_state::player_camera,
+    replay, run, run_headless,
+    screenshot::{self, SOFTWARE_ADAPTER},
+    settings::{Settings, SettingsOverrides, SETTINGS_PATH},
+    state::VIRTUAL_RESOLUTION,
+};
+use winit::dpi::PhysicalSize;
+
+#[derive(Parser)]
+#[command(about = "A 2D platformer")]
+struct Args {
+    /// Record every tick's input to a replay file.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["replay", "headless"])]
+    record: Option<PathBuf>,
+    /// Play a replay back without a window and check it ends up where the
+    /// recording did.
+    #[arg(long, value_name = "FILE", conflicts_with = "headless")]
+    replay: Option<PathBuf>,
+    /// Run this many ticks without a window or a GPU.
+    #[arg(long, value_name = "TICKS")]
+    headless: Option<u64>,
+    /// Draw the last headless tick on the software adapter and save it as a
+    /// PNG.
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    screenshot: Option<PathBuf>,
+    /// The settings file to read.
+    #[arg(long, value_name = "FILE", default_value = SETTINGS_PATH)]
+    settings: PathBuf,
+    #[command(flatten)]
+    overrides: SettingsOverrides,
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
-    run().await;
+
+    let args = Args::parse();
+
+    if let Some(path) = args.replay {
+        let checksum = replay::verify(&path).await?;
+        println!(
+            "replay {} matches, checksum {:016x}",
+            path.display(),
+            checksum
+        );
+    } else if let Some(ticks) = args.headless {
+        let world = run_headless(ticks, 0).await?;
+        println!(
+            "ran {} ticks, player at {}, checksum {:016x}",
+            world.ticks,
+            world.instance.position,
+            world.checksum()
+        );
+
+        if let Some(path) = args.screenshot {
+            let resolution = VIRTUAL_RESOLUTION;
+            let window_size = PhysicalSize::new(resolution.x, resolution.y);
+            let (camera, _) = player_camera(&world, window_size, resolution);
+            let image = screenshot::render_offscreen(&world, &camera, &SOFTWARE_ADAPTER).await?;
+            image
+                .save(&path)
+                .with_context(|| format!("failed to save screenshot to {}", path.display()))?;
+        }
+    } else {
+        let mut settings = Settings::load_or_default(&args.settings);
+        args.overrides.apply(&mut settings)?;
+        run(settings, args.record).await?;
+    }
+
+    Ok(())
 }
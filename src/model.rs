@@ -1,22 +1,28 @@
-use std::{collections::HashSet, ops::Range};
+use std::ops::Range;
 
 use bytemuck::{Pod, Zeroable};
 use glam::Vec2;
 use wgpu::{
-    BindGroup, Buffer, BufferAddress, IndexFormat, RenderPass, VertexAttribute, VertexBufferLayout,
-    VertexFormat, VertexStepMode,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferAddress, Device, IndexFormat,
+    RenderPass, SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension,
+    VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
 };
-use winit::event::VirtualKeyCode;
 
-use crate::{instance::Instance, Direction, Vertex};
+use crate::{
+    body::{Body, MovementProfile},
+    collision::{find_ledge, move_and_collide, Colliders, MoveOptions},
+    input::{Action, Axis, InputState},
+    instance::Instance,
+    texture::Texture,
+    Vertex,
+};
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct ModelVertex {
     pub position: [f32; 3],
-    // pub tex_coords: [f32; 2],
-    pub color: [f32; 3],
-    //    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
 }
 
 impl Vertex for ModelVertex {
@@ -35,12 +41,7 @@ impl Vertex for ModelVertex {
                 VertexAttribute {
                     offset: mem::size_of::<[f32; 3]>() as BufferAddress,
                     shader_location: 1,
-                    format: VertexFormat::Float32x3,
-                },
-                VertexAttribute {
-                    offset: mem::size_of::<[f32; 6]>() as BufferAddress,
-                    shader_location: 2,
-                    format: VertexFormat::Float32x3,
+                    format: VertexFormat::Float32x2,
                 },
             ],
         }
@@ -49,48 +50,355 @@ impl Vertex for ModelVertex {
 
 pub struct Material {
     pub name: String,
-    //pub diffuse_texture: texture::Texture,
+    pub diffuse_texture: Texture,
     pub bind_group: BindGroup,
 }
 
+impl Material {
+    pub fn new(
+        device: &Device,
+        name: &str,
+        diffuse_texture: Texture,
+        layout: &BindGroupLayout,
+    ) -> Self {
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&diffuse_texture.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+            ],
+            label: Some(name),
+        });
+
+        Self {
+            name: name.into(),
+            diffuse_texture,
+            bind_group,
+        }
+    }
+
+    /// The layout of a material's texture and sampler, shared by every
+    /// material so they can all be drawn with the same pipeline.
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        })
+    }
+}
+
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
     pub num_indices: u32,
-    //pub material: usize,
+    pub material: usize,
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
-    // pub materials: Vec<Material>,
+    pub materials: Vec<Material>,
+}
+
+/// What the player is doing, which decides how input moves them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovementState {
+    /// Running, jumping and falling, steered by input.
+    Free,
+    /// Pressed against a wall on `side` in the air, falling slowly.
+    WallSlide { side: f32 },
+    /// Pushed off a wall, with steering ignored for `ticks` more ticks.
+    WallJump { ticks: u32 },
+    /// Hanging from the corner of a ledge on `side`.
+    LedgeHang { side: f32, corner: Vec2 },
+    /// Pulling up onto a ledge, moving the instance from `from` to `to`.
+    LedgeClimb { from: Vec2, to: Vec2, tick: u32 },
 }
 
 pub struct ModelController {
-    pub speed: f32,
+    pub profile: MovementProfile,
+    pub state: MovementState,
     pub direction: Vec2,
+    /// `direction` as it was last tick, to tell fresh presses from held
+    /// ones.
+    pub previous_direction: Vec2,
+    pub jump_held: bool,
+    pub jump_requested: bool,
+    /// Ticks since the body was last on the ground.
+    pub airborne_ticks: u32,
+    /// Ticks since jump was last pressed.
+    pub jump_pressed_ticks: u32,
+    /// Whether the body is in the air because it jumped, rather than because
+    /// it fell.
+    pub jumping: bool,
+    /// Whether the current jump has already been cut short.
+    pub jump_cut: bool,
 }
 
 impl ModelController {
-    pub fn new(speed: f32) -> Self {
+    pub fn new(profile: MovementProfile) -> Self {
         Self {
-            speed,
+            profile,
+            state: MovementState::Free,
             direction: Vec2::ZERO,
+            previous_direction: Vec2::ZERO,
+            jump_held: false,
+            jump_requested: false,
+            airborne_ticks: u32::MAX,
+            jump_pressed_ticks: u32::MAX,
+            jumping: false,
+            jump_cut: false,
         }
     }
 
-    pub fn set_direction(&mut self, pressed_keys: &HashSet<VirtualKeyCode>) {
-        let directions: Vec2 = pressed_keys
-            .iter()
-            .filter_map(|key| Direction::from_virtual_keycode(key))
-            .map(|dir| dir.to_vec2())
-            .sum();
+    /// Reads this tick's movement and jump input.
+    pub fn set_input(&mut self, input: &InputState) {
+        self.direction = Vec2::new(input.axis(Axis::MoveX), input.axis(Axis::MoveY));
 
-        self.direction = directions.normalize_or_zero() * -1.;
+        // Only a fresh press jumps, holding the button doesn't bounce on
+        // landing
+        self.jump_requested = input.just_pressed(Action::Jump);
+        self.jump_held = input.pressed(Action::Jump);
     }
 
-    pub fn update_instance(&self, instance: &mut Instance) {
-        instance.position += self.direction * self.speed;
+    pub fn update_instance<C: Colliders + ?Sized>(
+        &mut self,
+        instance: &mut Instance,
+        body: &mut Body,
+        colliders: &C,
+        dt: f32,
+    ) {
+        self.step(instance, body, colliders, dt);
+        self.previous_direction = self.direction;
+    }
+
+    fn step<C: Colliders + ?Sized>(
+        &mut self,
+        instance: &mut Instance,
+        body: &mut Body,
+        colliders: &C,
+        dt: f32,
+    ) {
+        let profile = self.profile;
+
+        if body.grounded {
+            self.airborne_ticks = 0;
+            self.jumping = false;
+        } else {
+            self.airborne_ticks = self.airborne_ticks.saturating_add(1);
+        }
+
+        if self.jump_requested {
+            self.jump_pressed_ticks = 0;
+        } else {
+            self.jump_pressed_ticks = self.jump_pressed_ticks.saturating_add(1);
+        }
+
+        // Jumps pressed a little before landing still count, and so do jumps
+        // pressed a little after running off a ledge
+        let mut buffered =
+            self.jump_pressed_ticks <= MovementProfile::ticks(profile.jump_buffer_time, dt);
+        let coyote =
+            !self.jumping && self.airborne_ticks <= MovementProfile::ticks(profile.coyote_time, dt);
+        body.drop_through_ticks = body.drop_through_ticks.saturating_sub(1);
+
+        // Only states that steer set this, so nothing else drifts sideways
+        body.acceleration.x = 0.;
+
+        match self.state {
+            MovementState::LedgeHang { side, corner } => {
+                let bounds = body.bounds(instance.position);
+                if self.direction.y > 0.5
+                    || find_ledge(&bounds, side, profile.ledge_grab_reach, colliders)
+                        != Some(corner)
+                {
+                    // Let go, or the ledge moved away
+                    self.state = MovementState::Free;
+                } else if self.pressed_up() || self.pressed_towards(side) {
+                    // Up or towards the wall climbs onto the ledge, pressed
+                    // again after grabbing it. A jump pressed along with it,
+                    // e.g. bound to the same key, doesn't go off once the
+                    // climb is over.
+                    self.jump_pressed_ticks = u32::MAX;
+                    let edge = if side > 0. {
+                        bounds.min.x
+                    } else {
+                        bounds.max.x
+                    };
+                    self.state = MovementState::LedgeClimb {
+                        from: instance.position,
+                        to: instance.position + Vec2::new(corner.x - edge, corner.y - bounds.max.y),
+                        tick: 0,
+                    };
+                    return;
+                } else if buffered {
+                    self.state = MovementState::Free;
+                    self.jump(body);
+                    // A normal jump, not a wall jump off the side of the
+                    // ledge
+                    buffered = false;
+                    body.wall = 0.;
+                } else {
+                    return;
+                }
+            }
+            MovementState::LedgeClimb { from, to, tick } => {
+                let tick = tick + 1;
+                let ticks = MovementProfile::ticks(profile.ledge_climb_time, dt).max(1);
+                let t = tick as f32 / ticks as f32;
+
+                // Up first, then over, so the body doesn't cut through the
+                // corner
+                instance.position = Vec2::new(
+                    from.x + (to.x - from.x) * (2. * t - 1.).clamp(0., 1.),
+                    from.y + (to.y - from.y) * (2. * t).min(1.),
+                );
+                body.velocity = Vec2::ZERO;
+
+                if tick >= ticks {
+                    self.state = MovementState::Free;
+                    body.grounded = true;
+                } else {
+                    self.state = MovementState::LedgeClimb { from, to, tick };
+                }
+                return;
+            }
+            MovementState::WallJump { ticks } if ticks > 0 && !body.grounded => {
+                self.state = MovementState::WallJump { ticks: ticks - 1 };
+            }
+            _ => {
+                let acceleration = if body.grounded {
+                    profile.run_acceleration
+                } else {
+                    profile.air_acceleration
+                };
+                let target = self.direction.x * profile.speed;
+                body.acceleration.x =
+                    ((target - body.velocity.x) / dt).clamp(-acceleration, acceleration);
+
+                // Holding towards a wall while falling slides down it
+                let against_wall =
+                    !body.grounded && body.wall != 0. && self.direction.x * body.wall > 0.5;
+                self.state = if against_wall && body.velocity.y > 0. {
+                    MovementState::WallSlide { side: body.wall }
+                } else {
+                    MovementState::Free
+                };
+            }
+        }
+
+        if buffered && body.grounded && body.on_one_way && self.direction.y > 0.5 {
+            // Down and jump on a one-way platform drops through it instead
+            body.drop_through_ticks = MovementProfile::ticks(profile.drop_through_time, dt);
+            body.grounded = false;
+            self.jump_pressed_ticks = u32::MAX;
+        } else if buffered && (body.grounded || coyote) {
+            self.jump(body);
+        } else if buffered && body.wall != 0. {
+            body.velocity = Vec2::new(-body.wall * profile.wall_jump.x, -profile.wall_jump.y);
+            body.acceleration.x = 0.;
+            self.jumping = true;
+            self.jump_cut = false;
+            self.jump_pressed_ticks = u32::MAX;
+            self.state = MovementState::WallJump {
+                ticks: MovementProfile::ticks(profile.wall_jump_lock_time, dt),
+            };
+        }
+
+        // Letting go on the way up cuts the jump short
+        if self.jumping && !self.jump_held && !self.jump_cut && body.velocity.y < 0. {
+            body.velocity.y *= profile.jump_cut;
+            self.jump_cut = true;
+        }
+
+        let mut gravity = profile.jump.gravity();
+        if self.jumping && self.jump_held && body.velocity.y.abs() < profile.apex_threshold {
+            gravity *= profile.apex_gravity_scale;
+        }
+
+        let mut motion = body.integrate(gravity, dt);
+        if let MovementState::WallSlide { .. } = self.state {
+            if body.velocity.y > profile.wall_slide_speed {
+                body.velocity.y = profile.wall_slide_speed;
+                motion.y = body.velocity.y * dt;
+            }
+        }
+
+        let collision = move_and_collide(
+            &body.bounds(instance.position),
+            motion,
+            colliders,
+            MoveOptions {
+                drop_through: body.drop_through_ticks > 0,
+                stick_to_ground: body.grounded && body.velocity.y >= 0.,
+            },
+        );
+
+        instance.position += collision.motion;
+        body.apply_contacts(&collision.contacts);
+
+        // Falling past the top of a wall while holding towards it grabs on
+        let grabbing = !body.grounded
+            && body.velocity.y >= 0.
+            && self.direction.y <= 0.5
+            && body.wall != 0.
+            && self.direction.x * body.wall > 0.5
+            && matches!(
+                self.state,
+                MovementState::Free | MovementState::WallSlide { .. }
+            );
+        if grabbing {
+            let bounds = body.bounds(instance.position);
+            let reach = collision.motion.y + profile.ledge_grab_reach;
+            if let Some(corner) = find_ledge(&bounds, body.wall, reach, colliders) {
+                instance.position.y += corner.y - bounds.min.y;
+                body.velocity = Vec2::ZERO;
+                self.jumping = false;
+                self.state = MovementState::LedgeHang {
+                    side: body.wall,
+                    corner,
+                };
+            }
+        }
+    }
+
+    fn pressed_up(&self) -> bool {
+        self.direction.y < -0.5 && self.previous_direction.y >= -0.5
+    }
+
+    fn pressed_towards(&self, side: f32) -> bool {
+        self.direction.x * side > 0.5 && self.previous_direction.x * side <= 0.5
+    }
+
+    fn jump(&mut self, body: &mut Body) {
+        body.velocity.y = -self.profile.jump.jump_velocity();
+        body.grounded = false;
+        self.jumping = true;
+        self.jump_cut = false;
+        self.jump_pressed_ticks = u32::MAX;
     }
 }
 
@@ -98,14 +406,14 @@ pub trait DrawModel<'a> {
     fn draw_mesh(
         &mut self,
         mesh: &'a Mesh,
-        // material: &'a Material,
+        material: &'a Material,
         camera_bind_group: &'a BindGroup,
     );
 
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
-        // material: &'a Material,
+        material: &'a Material,
         instances: Range<u32>,
         camera_bind_group: &'a BindGroup,
     );
@@ -118,6 +426,14 @@ pub trait DrawModel<'a> {
         instances: Range<u32>,
         camera_bind_group: &'a BindGroup,
     );
+
+    fn draw_model_instanced_with_material(
+        &mut self,
+        model: &'a Model,
+        material: &'a Material,
+        instances: Range<u32>,
+        camera_bind_group: &'a BindGroup,
+    );
 }
 
 impl<'a, 'b> DrawModel<'b> for RenderPass<'a>
@@ -127,24 +443,23 @@ where
     fn draw_mesh(
         &mut self,
         mesh: &'a Mesh,
-        // material: &'a Material,
+        material: &'a Material,
         camera_bind_group: &'a BindGroup,
     ) {
-        // self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group);
-        self.draw_mesh_instanced(mesh, 0..1, camera_bind_group);
+        self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group);
     }
 
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
-        //material: &'a Material,
+        material: &'a Material,
         instances: Range<u32>,
         camera_bind_group: &'a BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
         self.set_index_buffer(mesh.index_buffer.slice(..), IndexFormat::Uint32);
-        //self.set_bind_group(0, &material.bind_group, &[]);
-        self.set_bind_group(0, camera_bind_group, &[]);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, camera_bind_group, &[]);
         self.draw_indexed(0..mesh.num_indices, 0, instances);
     }
 
@@ -159,9 +474,466 @@ where
         camera_bind_group: &'a BindGroup,
     ) {
         for mesh in &model.meshes {
-            // let material = &model.materials[mesh.material];
-            // self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group);
-            self.draw_mesh_instanced(mesh, instances.clone(), camera_bind_group);
+            let material = &model.materials[mesh.material];
+            self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group);
+        }
+    }
+
+    fn draw_model_instanced_with_material(
+        &mut self,
+        model: &'a Model,
+        material: &'a Material,
+        instances: Range<u32>,
+        camera_bind_group: &'a BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+
+    use super::*;
+    use crate::{body::JumpProfile, collision::Aabb, tilemap::TileMap};
+
+    const DT: f32 = 1. / 60.;
+
+    /// The player's movement, as set up by the world.
+    fn profile() -> MovementProfile {
+        MovementProfile {
+            speed: 120.,
+            jump: JumpProfile {
+                jump_height: 56.,
+                time_to_apex: 0.35,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// A controller with a 16x16 body, settled onto the ground of `layout`
+    /// at `position`.
+    struct Player {
+        controller: ModelController,
+        instance: Instance,
+        body: Body,
+        map: TileMap,
+    }
+
+    impl Player {
+        fn new(layout: &str, position: Vec2) -> Self {
+            let mut player = Self {
+                controller: ModelController::new(profile()),
+                instance: Instance {
+                    position,
+                    rotation: 0.,
+                    scale: 16.,
+                    tint: Vec4::ONE,
+                    uv_rect: Instance::FULL_UV_RECT,
+                },
+                body: Body::new(Aabb::from_position_size(Vec2::ZERO, Vec2::splat(16.)), 500.),
+                map: TileMap::from_ascii(layout, 16.).unwrap(),
+            };
+            for _ in 0..30 {
+                player.tick(Vec2::ZERO, false, false);
+            }
+            assert!(player.body.grounded);
+            player
+        }
+
+        fn tick(&mut self, direction: Vec2, jump_pressed: bool, jump_held: bool) {
+            self.controller.direction = direction;
+            self.controller.jump_requested = jump_pressed;
+            self.controller.jump_held = jump_pressed || jump_held;
+            self.controller
+                .update_instance(&mut self.instance, &mut self.body, &self.map, DT);
+        }
+
+        /// Jumps at the wall to the right and holds towards it until sliding
+        /// down it.
+        fn slide_down_wall(&mut self) {
+            for tick in 0..90 {
+                self.tick(Vec2::X, tick == 0, true);
+                if let MovementState::WallSlide { .. } = self.controller.state {
+                    return;
+                }
+            }
+            panic!("never started sliding down the wall");
+        }
+
+        /// Jumps at the wall to the right and holds towards it until the
+        /// ledge is grabbed.
+        fn grab_ledge(&mut self) {
+            for tick in 0..90 {
+                self.tick(Vec2::X, tick == 0, tick < 30);
+                if let MovementState::LedgeHang { .. } = self.controller.state {
+                    return;
+                }
+            }
+            panic!("never grabbed the ledge");
+        }
+    }
+
+    /// A room with walls too tall to grab the top of.
+    const WALLS: &str = "
+#..............#
+#..............#
+#..............#
+#..............#
+#..............#
+#..............#
+#..............#
+#..............#
+#..............#
+################";
+
+    #[test]
+    fn wall_slide_caps_the_fall_while_held_towards_the_wall() {
+        let mut player = Player::new(WALLS, Vec2::new(200., 100.));
+        player.slide_down_wall();
+        assert_eq!(player.instance.position.x, 224.);
+
+        let mut capped = false;
+        for _ in 0..30 {
+            player.tick(Vec2::X, false, true);
+            assert_eq!(
+                player.controller.state,
+                MovementState::WallSlide { side: 1. }
+            );
+            assert!(player.body.velocity.y <= 60.);
+            capped |= player.body.velocity.y == 60.;
+        }
+        assert!(capped, "never reached the wall slide speed");
+
+        // Letting go of the wall falls at the normal speed again
+        for _ in 0..5 {
+            player.tick(Vec2::ZERO, false, true);
+        }
+        assert_eq!(player.controller.state, MovementState::Free);
+        assert!(player.body.velocity.y > 60.);
+    }
+
+    #[test]
+    fn falling_without_holding_towards_the_wall_doesnt_slide() {
+        let mut player = Player::new(WALLS, Vec2::new(200., 100.));
+        player.slide_down_wall();
+
+        player.tick(Vec2::NEG_X, false, true);
+        assert_eq!(player.controller.state, MovementState::Free);
+    }
+
+    #[test]
+    fn wall_jump_pushes_off_the_wall() {
+        let mut player = Player::new(WALLS, Vec2::new(200., 100.));
+        player.slide_down_wall();
+        for _ in 0..10 {
+            player.tick(Vec2::X, false, true);
+        }
+
+        let gravity = player.controller.profile.jump.gravity();
+        player.tick(Vec2::X, true, true);
+        assert!(matches!(
+            player.controller.state,
+            MovementState::WallJump { .. }
+        ));
+        assert_eq!(player.body.velocity, Vec2::new(-160., -280. + gravity * DT));
+        assert!(player.instance.position.x < 224.);
+    }
+
+    #[test]
+    fn wall_jump_ignores_steering_for_nine_ticks() {
+        let mut player = Player::new(WALLS, Vec2::new(200., 100.));
+        player.slide_down_wall();
+        player.tick(Vec2::X, true, true);
+
+        // 0.15s at 60 ticks a second
+        for tick in 1..=9 {
+            player.tick(Vec2::X, false, true);
+            assert_eq!(player.body.velocity.x, -160., "steered on tick {}", tick);
+        }
+
+        // Then steering turns the body around at the air acceleration
+        player.tick(Vec2::X, false, true);
+        assert_eq!(player.controller.state, MovementState::Free);
+        assert_eq!(player.body.velocity.x, -140.);
+
+        let mut previous = player.body.velocity.x;
+        for _ in 0..20 {
+            player.tick(Vec2::X, false, true);
+            assert!(player.body.velocity.x >= previous);
+            assert!(player.body.velocity.x <= 120.);
+            previous = player.body.velocity.x;
+        }
+        assert_eq!(player.body.velocity.x, 120.);
+    }
+
+    #[test]
+    fn jumping_on_the_ground_next_to_a_wall_is_a_normal_jump() {
+        let mut player = Player::new(WALLS, Vec2::new(224., 100.));
+        player.tick(Vec2::X, false, false);
+        assert_eq!(player.body.wall, 1.);
+
+        player.tick(Vec2::X, true, true);
+        assert_eq!(player.controller.state, MovementState::Free);
+        assert_eq!(player.body.velocity.x, 0.);
+        assert!(player.body.velocity.y < 0.);
+    }
+
+    const LEDGE: &str = "
+#..............#
+#..............#
+#..............#
+#.........######
+#.........#....#
+#.........#....#
+#.........#....#
+################";
+
+    #[test]
+    fn ledge_hang_lasts_while_held_towards_the_wall() {
+        let mut player = Player::new(LEDGE, Vec2::new(120., 90.));
+        player.grab_ledge();
+        assert_eq!(player.instance.position.y, 48.);
+
+        for _ in 0..60 {
+            player.tick(Vec2::X, false, false);
+            assert!(matches!(
+                player.controller.state,
+                MovementState::LedgeHang { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn ledge_jump_is_a_normal_jump() {
+        let mut player = Player::new(LEDGE, Vec2::new(120., 90.));
+        player.grab_ledge();
+
+        player.tick(Vec2::X, true, true);
+        assert_eq!(player.controller.state, MovementState::Free);
+        assert_eq!(player.body.velocity.x, 0., "jumped off the ledge sideways");
+        assert!(player.body.velocity.y < 0.);
+    }
+
+    #[test]
+    fn pressing_up_climbs_even_with_jump_pressed_too() {
+        let mut player = Player::new(LEDGE, Vec2::new(120., 90.));
+        player.grab_ledge();
+
+        // Up and jump pressed together, e.g. bound to the same key
+        player.tick(Vec2::new(1., -1.), true, true);
+        assert!(matches!(
+            player.controller.state,
+            MovementState::LedgeClimb { .. }
+        ));
+
+        for _ in 0..30 {
+            player.tick(Vec2::ZERO, false, true);
+        }
+        assert_eq!(player.instance.position, Vec2::new(160., 32.));
+        assert!(player.body.grounded);
+        assert_eq!(player.body.velocity, Vec2::ZERO, "jumped after climbing");
+    }
+
+    #[test]
+    fn pressing_towards_the_wall_again_climbs() {
+        let mut player = Player::new(LEDGE, Vec2::new(120., 90.));
+        player.grab_ledge();
+
+        player.tick(Vec2::ZERO, false, false);
+        player.tick(Vec2::X, false, false);
+        assert!(matches!(
+            player.controller.state,
+            MovementState::LedgeClimb { .. }
+        ));
+    }
+
+    #[test]
+    fn pressing_down_lets_go() {
+        let mut player = Player::new(LEDGE, Vec2::new(120., 90.));
+        player.grab_ledge();
+
+        for _ in 0..10 {
+            player.tick(Vec2::new(1., 1.), false, false);
+        }
+        assert!(player.instance.position.y > 48.);
+        assert!(!matches!(
+            player.controller.state,
+            MovementState::LedgeHang { .. }
+        ));
+    }
+
+    const FLOOR: &str = "
+..........................................
+..........................................
+..........................................
+..........................................
+..........................................
+..........................................
+..........................................
+..........................................
+##########################################";
+
+    const LEDGE_DROP: &str = "
+..........................................
+..........................................
+..........................................
+..........................................
+..........................................
+#######...................................
+..........................................
+..........................................
+##########################################";
+
+    /// The body's horizontal velocity after each of `ticks` ticks of holding
+    /// `direction`.
+    fn run_velocities(player: &mut Player, direction: Vec2, ticks: usize) -> Vec<f32> {
+        (0..ticks)
+            .map(|_| {
+                player.tick(direction, false, false);
+                // Rounded, as the steps are a tick's worth of acceleration
+                (player.body.velocity.x * 1000.).round() / 1000.
+            })
+            .collect()
+    }
+
+    #[test]
+    fn running_speeds_up_and_slows_down() {
+        let mut player = Player::new(FLOOR, Vec2::new(64., 100.));
+
+        // 1800 pixels per second squared is 30 pixels per second a tick
+        assert_eq!(
+            run_velocities(&mut player, Vec2::X, 5),
+            [30., 60., 90., 120., 120.]
+        );
+        assert_eq!(
+            run_velocities(&mut player, Vec2::ZERO, 5),
+            [90., 60., 30., 0., 0.]
+        );
+        assert_eq!(
+            run_velocities(&mut player, -Vec2::X, 9),
+            [-30., -60., -90., -120., -120., -120., -120., -120., -120.]
+        );
+    }
+
+    #[test]
+    fn steering_in_the_air_is_looser() {
+        let mut player = falling_player();
+
+        // 1200 pixels per second squared is 20 pixels per second a tick
+        assert_eq!(run_velocities(&mut player, Vec2::X, 3), [20., 40., 60.]);
+        assert!(!player.body.grounded);
+    }
+
+    /// Whether pressing jump `ticks` ticks after running off a ledge jumps.
+    fn coyote_jumps(ticks: u32) -> bool {
+        let mut player = Player::new(LEDGE_DROP, Vec2::new(64., 60.));
+        while player.body.grounded {
+            player.tick(Vec2::X, false, false);
+        }
+        for _ in 1..ticks {
+            player.tick(Vec2::X, false, false);
+        }
+        player.tick(Vec2::X, true, true);
+        player.body.velocity.y < 0.
+    }
+
+    /// A player in the air above the floor, about to fall onto it.
+    fn falling_player() -> Player {
+        let mut player = Player::new(FLOOR, Vec2::new(64., 100.));
+        player.instance.position.y = 40.;
+        player.body.grounded = false;
+        player
+    }
+
+    /// Whether pressing jump `ticks` ticks before the tick the body lands
+    /// on jumps once it's down.
+    fn buffered_jumps(ticks: u32) -> bool {
+        let mut landing = falling_player();
+        let mut landed_at = 0;
+        while !landing.body.grounded {
+            landing.tick(Vec2::ZERO, false, false);
+            landed_at += 1;
+        }
+
+        let mut player = falling_player();
+        for tick in 0..landed_at + 10 {
+            player.tick(Vec2::ZERO, tick + ticks == landed_at, false);
+            if player.body.velocity.y < 0. {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The body's vertical velocity after each tick of a jump, with jump
+    /// held for the first `held` ticks.
+    fn jump_velocities(held: usize) -> Vec<(f32, bool)> {
+        let mut player = Player::new(FLOOR, Vec2::new(64., 100.));
+        (0..60)
+            .map(|tick| {
+                player.tick(Vec2::ZERO, tick == 0, tick < held);
+                (player.body.velocity.y, player.controller.jump_cut)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn coyote_time_lasts_six_ticks() {
+        assert_eq!(MovementProfile::ticks(profile().coyote_time, DT), 6);
+        assert!(coyote_jumps(1));
+        assert!(coyote_jumps(6));
+        assert!(!coyote_jumps(7));
+    }
+
+    #[test]
+    fn jump_buffer_lasts_six_ticks() {
+        assert_eq!(MovementProfile::ticks(profile().jump_buffer_time, DT), 6);
+        assert!(buffered_jumps(0));
+        assert!(buffered_jumps(6));
+        assert!(!buffered_jumps(7));
+    }
+
+    #[test]
+    fn jump_cut_applies_until_the_last_rising_tick() {
+        let held = jump_velocities(usize::MAX);
+        let jump_cut = profile().jump_cut;
+        let gravity_per_tick = profile().jump.gravity() * DT;
+
+        // Still rising going into tick 22, so letting go then cuts
+        let released = jump_velocities(22);
+        assert!(held[21].0 < 0.);
+        assert_eq!(released.iter().position(|(_, cut)| *cut), Some(22));
+        assert_eq!(released[22].0, held[21].0 * jump_cut + gravity_per_tick);
+
+        // Falling by tick 23, so it's too late
+        assert!(held[22].0 >= 0.);
+        let released = jump_velocities(23);
+        assert!(released.iter().all(|(_, cut)| !cut));
+    }
+
+    #[test]
+    fn apex_hang_lasts_from_tick_19_to_28() {
+        let gravity_per_tick = profile().jump.gravity() * DT;
+        let hanging_ticks = |velocities: &[(f32, bool)]| -> Vec<usize> {
+            velocities
+                .windows(2)
+                .enumerate()
+                .filter(|(_, pair)| {
+                    let dv = pair[1].0 - pair[0].0;
+                    (dv - gravity_per_tick * 0.5).abs() < 1e-3
+                })
+                .map(|(tick, _)| tick + 1)
+                .collect()
+        };
+
+        let held = jump_velocities(usize::MAX);
+        assert_eq!(hanging_ticks(&held), (19..=28).collect::<Vec<_>>());
+
+        // Only while jump is held
+        let released = jump_velocities(1);
+        assert!(hanging_ticks(&released).is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+use glam::Vec2;
+
+use crate::{
+    body::Body,
+    collision::{move_and_collide, Aabb, Collider, Colliders, MoveOptions},
+    instance::Instance,
+    level::{Level, LevelObject},
+    tween::Easing,
+};
+
+/// How close the bottom of a body has to be to the top of a platform for the
+/// body to count as standing on it.
+const RIDE_DISTANCE: f32 = 0.5;
+
+/// How far a body can overlap a platform before it counts as crushed, to
+/// allow for floating point error.
+const CRUSH_TOLERANCE: f32 = 0.05;
+
+/// What a path does when it gets to its last point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathMode {
+    /// Stop there.
+    Linear,
+    /// Turn around and go back the way it came.
+    #[default]
+    PingPong,
+    /// Carry on from the last point back to the first.
+    Loop,
+}
+
+impl PathMode {
+    /// Parses `"linear"`, `"ping_pong"` or `"loop"`, as used in level files.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(PathMode::Linear),
+            "ping_pong" => Some(PathMode::PingPong),
+            "loop" => Some(PathMode::Loop),
+            _ => None,
+        }
+    }
+}
+
+/// A route through the level made of straight segments between waypoints.
+#[derive(Clone, Debug)]
+pub struct PlatformPath {
+    pub points: Vec<Vec2>,
+    pub mode: PathMode,
+    /// How fast the path is followed, in pixels per second. With easing,
+    /// that's the average over each segment.
+    pub speed: f32,
+    /// How each segment speeds up and slows down.
+    pub easing: Easing,
+    /// How long to wait at each waypoint, in seconds.
+    pub wait: f32,
+}
+
+/// A platform following a path, carrying whatever stands on it.
+#[derive(Clone, Debug)]
+pub struct MovingPlatform {
+    pub path: PlatformPath,
+    pub size: Vec2,
+    /// Whether the platform can be jumped up through and dropped down
+    /// through, rather than blocking from every side.
+    pub one_way: bool,
+    /// The tile the platform is drawn with, if it's drawn at all.
+    pub tile: Option<u32>,
+    /// The top-left corner.
+    pub position: Vec2,
+    /// Where the platform was before the last tick, to draw in between ticks.
+    pub previous_position: Vec2,
+    /// The waypoints of the segment being followed.
+    from: usize,
+    to: usize,
+    /// Whether a ping-pong path is being followed forwards.
+    forward: bool,
+    /// How long the platform has been on the current segment, in seconds.
+    elapsed: f32,
+    /// How much longer to wait at the last waypoint, in seconds.
+    waiting: f32,
+    stopped: bool,
+}
+
+impl MovingPlatform {
+    /// A platform with its top-left corner at the start of `path`. Panics if
+    /// the path has no points.
+    pub fn new(path: PlatformPath, size: Vec2, one_way: bool) -> Self {
+        let position = path.points[0];
+        let stopped = path.points.len() < 2;
+
+        Self {
+            size,
+            one_way,
+            tile: None,
+            position,
+            previous_position: position,
+            from: 0,
+            to: if stopped { 0 } else { 1 },
+            forward: true,
+            elapsed: 0.,
+            waiting: path.wait,
+            stopped,
+            path,
+        }
+    }
+
+    /// Every `moving_platform` polyline object in `level`. The line traces
+    /// the platform's top-left corner.
+    pub fn from_level(level: &Level) -> Vec<Self> {
+        level
+            .objects_of_class("moving_platform")
+            .filter_map(Self::from_object)
+            .collect()
+    }
+
+    fn from_object(object: &LevelObject) -> Option<Self> {
+        let Some(points) = object.polyline.clone().filter(|points| !points.is_empty()) else {
+            tracing::warn!(
+                id = object.id,
+                "moving platform isn't a polyline, ignoring it"
+            );
+            return None;
+        };
+
+        let f32_property = |name: &str, default: f32| {
+            object
+                .property(name)
+                .and_then(|value| value.as_f32())
+                .unwrap_or(default)
+        };
+        let mode = match object.property("mode").and_then(|mode| mode.as_str()) {
+            Some(name) => PathMode::from_name(name).unwrap_or_else(|| {
+                tracing::warn!(id = object.id, mode = name, "unknown path mode");
+                PathMode::default()
+            }),
+            None => PathMode::default(),
+        };
+        let easing = match object.property("easing").and_then(|easing| easing.as_str()) {
+            Some(name) => Easing::from_name(name).unwrap_or_else(|| {
+                tracing::warn!(id = object.id, easing = name, "unknown easing");
+                Easing::default()
+            }),
+            None => Easing::default(),
+        };
+
+        let path = PlatformPath {
+            points,
+            mode,
+            speed: f32_property("speed", 60.),
+            easing,
+            wait: f32_property("wait", 0.),
+        };
+        let size = Vec2::new(f32_property("width", 48.), f32_property("height", 16.));
+        let one_way = object
+            .property("one_way")
+            .and_then(|one_way| one_way.as_bool())
+            .unwrap_or(false);
+
+        let mut platform = Self::new(path, size, one_way);
+        platform.tile = object
+            .property("tile")
+            .and_then(|tile| tile.as_i64())
+            .map(|tile| tile as u32);
+        Some(platform)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_position_size(self.position, self.size)
+    }
+
+    pub fn collider(&self) -> Collider {
+        if self.one_way {
+            Collider::one_way(self.bounds())
+        } else {
+            Collider::solid(self.bounds())
+        }
+    }
+
+    /// Moves the platform along its path by `dt` seconds and returns how far
+    /// it went.
+    pub fn update(&mut self, dt: f32) -> Vec2 {
+        self.previous_position = self.position;
+
+        // Each pass finishes a wait or a segment, so this only runs more than
+        // once when a tick crosses a waypoint. The limit keeps a path whose
+        // points are all in the same place from going round forever.
+        let mut remaining = dt;
+        for _ in 0..=2 * self.path.points.len() {
+            if self.stopped || remaining <= 0. {
+                break;
+            }
+
+            if self.waiting > 0. {
+                let waited = self.waiting.min(remaining);
+                self.waiting -= waited;
+                remaining -= waited;
+                continue;
+            }
+
+            let duration = self.segment_duration();
+            let step = (duration - self.elapsed).min(remaining);
+            self.elapsed += step;
+            remaining -= step;
+            if self.elapsed >= duration {
+                self.arrive();
+            }
+        }
+
+        self.position = self.path_position();
+        self.position - self.previous_position
+    }
+
+    fn segment_duration(&self) -> f32 {
+        let length = self.path.points[self.from].distance(self.path.points[self.to]);
+        if self.path.speed > 0. {
+            length / self.path.speed
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    fn path_position(&self) -> Vec2 {
+        let duration = self.segment_duration();
+        let t = if duration > 0. {
+            self.elapsed / duration
+        } else {
+            1.
+        };
+
+        self.path.points[self.from].lerp(self.path.points[self.to], self.path.easing.apply(t))
+    }
+
+    /// Moves on to the next segment after reaching waypoint `to`.
+    fn arrive(&mut self) {
+        let last = self.path.points.len() - 1;
+        self.elapsed = 0.;
+        self.waiting = self.path.wait;
+        self.from = self.to;
+
+        match self.path.mode {
+            PathMode::Linear => {
+                if self.to < last {
+                    self.to += 1;
+                } else {
+                    self.stopped = true;
+                }
+            }
+            PathMode::PingPong => {
+                if (self.forward && self.to == last) || (!self.forward && self.to == 0) {
+                    self.forward = !self.forward;
+                }
+                self.to = if self.forward {
+                    self.to + 1
+                } else {
+                    self.to - 1
+                };
+            }
+            PathMode::Loop => self.to = (self.to + 1) % (last + 1),
+        }
+    }
+
+    /// Whether a body with `bounds` is standing on the platform.
+    fn carries(&self, body: &Body, bounds: &Aabb) -> bool {
+        let platform = self.bounds();
+        body.grounded
+            && (bounds.max.y - platform.min.y).abs() <= RIDE_DISTANCE
+            && bounds.max.x > platform.min.x
+            && bounds.min.x < platform.max.x
+    }
+}
+
+impl Colliders for [MovingPlatform] {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        out.extend(
+            self.iter()
+                .map(MovingPlatform::collider)
+                .filter(|collider| collider.bounds.intersects(region)),
+        );
+    }
+}
+
+impl Colliders for Vec<MovingPlatform> {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        self.as_slice().colliders_in(region, out);
+    }
+}
+
+/// Moves every platform along its path by `dt` seconds. A body standing on a
+/// platform moves with it, and a body a solid platform runs into gets pushed
+/// out of the way, either way only as far as `colliders` let it. Returns
+/// whether the body got crushed between a platform and something it couldn't
+/// be pushed through.
+pub fn update_platforms<C: Colliders + ?Sized>(
+    platforms: &mut [MovingPlatform],
+    instance: &mut Instance,
+    body: &mut Body,
+    colliders: &C,
+    dt: f32,
+) -> bool {
+    let mut crushed = false;
+    for platform in platforms {
+        let before = platform.bounds();
+        let bounds = body.bounds(instance.position);
+        let riding = platform.carries(body, &bounds);
+
+        let delta = platform.update(dt);
+        if delta == Vec2::ZERO {
+            continue;
+        }
+        let after = platform.bounds();
+
+        let push = if riding {
+            delta
+        } else if !platform.one_way {
+            push_out(&bounds, &before, &after)
+        } else {
+            Vec2::ZERO
+        };
+        if push == Vec2::ZERO {
+            continue;
+        }
+
+        let collision = move_and_collide(&bounds, push, colliders, MoveOptions::default());
+        instance.position += collision.motion;
+        if push.y < 0. {
+            body.velocity.y = body.velocity.y.min(0.);
+        } else if push.y > 0. {
+            body.velocity.y = body.velocity.y.max(0.);
+        }
+
+        let bounds = body.bounds(instance.position);
+        let solid = Aabb::new(after.min + CRUSH_TOLERANCE, after.max - CRUSH_TOLERANCE);
+        if !platform.one_way && bounds.intersects(&solid) {
+            crushed = true;
+        }
+    }
+
+    crushed
+}
+
+/// How far a body with `bounds` has to move to get out of the way of a
+/// platform that moved from `before` to `after`, pushing it along the side
+/// the platform came at it from.
+fn push_out(bounds: &Aabb, before: &Aabb, after: &Aabb) -> Vec2 {
+    if !bounds.intersects(after) {
+        return Vec2::ZERO;
+    }
+
+    if bounds.min.x >= before.max.x - CRUSH_TOLERANCE {
+        Vec2::new(after.max.x - bounds.min.x, 0.)
+    } else if bounds.max.x <= before.min.x + CRUSH_TOLERANCE {
+        Vec2::new(after.min.x - bounds.max.x, 0.)
+    } else if bounds.min.y >= before.max.y - CRUSH_TOLERANCE {
+        Vec2::new(0., after.max.y - bounds.min.y)
+    } else if bounds.max.y <= before.min.y + CRUSH_TOLERANCE {
+        Vec2::new(0., after.min.y - bounds.max.y)
+    } else {
+        // Already inside it, which only happens if something else put it
+        // there
+        Vec2::ZERO
+    }
+}
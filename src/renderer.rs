@@ -0,0 +1,439 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{Context, Result};
+use glam::{Mat4, UVec2, Vec2, Vec4};
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, Color,
+    CommandEncoder, Device, Extent3d, LoadOp, Operations, PipelineLayout, Queue,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, ShaderStages,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor,
+};
+
+use crate::{
+    atlas::{AtlasBuilder, AtlasRegion},
+    camera::{Camera, CameraUniform},
+    instance::{Instance, InstanceRaw},
+    level::Level,
+    model::{DrawModel, Material, Mesh, Model, ModelVertex},
+    sprite_batch::{Sprite, SpriteBatch},
+    world::{player_animations, World},
+    Vertex,
+};
+
+/// Draws the world. Owns every GPU resource the game needs, so the world and
+/// the rest of the game state can run without a GPU.
+pub struct Renderer {
+    pub render_pipeline: RenderPipeline,
+    pub render_pipeline_layout: PipelineLayout,
+    /// The format of the targets drawn to.
+    pub format: TextureFormat,
+    /// The size of the targets drawn to.
+    pub target_size: UVec2,
+    pub sample_count: u32,
+    /// What gets drawn to before being resolved to the target, when drawing
+    /// with more than one sample per pixel.
+    pub msaa_target: Option<TextureView>,
+    pub camera_uniform: CameraUniform,
+    pub camera_buffer: Buffer,
+    pub camera_bind_group: BindGroup,
+    pub camera_bind_group_layout: BindGroupLayout,
+    pub model: Model,
+    pub sprite_batch: SpriteBatch,
+    /// Every image in the sprite atlas, by name.
+    pub sprite_regions: HashMap<String, AtlasRegion>,
+    /// Where each of the level's tilesets is in the sprite atlas.
+    pub tileset_regions: Vec<AtlasRegion>,
+    pub clear_color: Color,
+}
+
+impl Renderer {
+    /// Loads everything needed to draw `level` into `target_size` targets of
+    /// `format`, with `sample_count` samples per pixel.
+    pub async fn new(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        target_size: UVec2,
+        sample_count: u32,
+        level: &Level,
+        texture_bind_group_layout: &BindGroupLayout,
+    ) -> Result<Self> {
+        // Everything is drawn from one atlas, so the whole frame can be drawn
+        // in as few batches as possible
+        let mut atlas_builder = AtlasBuilder::new(2);
+        let player_frames: BTreeSet<String> = player_animations()
+            .into_values()
+            .flat_map(|clip| clip.frames.into_iter().map(|frame| frame.region))
+            .collect();
+        for frame in &player_frames {
+            atlas_builder.load(frame).await?;
+        }
+        for tileset in &level.tilesets {
+            // Tilesets without an image are collections of individual images,
+            // which can't be drawn as a grid
+            let image = tileset.image.as_deref().with_context(|| {
+                format!(
+                    "tileset {} is an image collection, which isn't supported",
+                    tileset.name
+                )
+            })?;
+            atlas_builder.load(image).await?;
+        }
+        let atlas = atlas_builder.build(device, queue, "Sprite Atlas")?;
+
+        let sprite_regions = atlas.regions;
+        let tileset_regions = level
+            .tilesets
+            .iter()
+            .map(|tileset| sprite_regions[tileset.image.as_deref().unwrap_or_default()])
+            .collect();
+
+        let camera_uniform = CameraUniform {
+            view_proj: Mat4::IDENTITY,
+        };
+        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        // A unit quad with its origin in the top-left corner, matching both
+        // the y-down world and texture coordinates.
+        let vertices = &[
+            ModelVertex {
+                position: [0., 0., 0.],
+                tex_coords: [0., 0.],
+            },
+            ModelVertex {
+                position: [1., 0., 0.],
+                tex_coords: [1., 0.],
+            },
+            ModelVertex {
+                position: [1., 1., 0.],
+                tex_coords: [1., 1.],
+            },
+            ModelVertex {
+                position: [0., 1., 0.],
+                tex_coords: [0., 1.],
+            },
+        ];
+
+        let indices = &[0, 1, 2, 2, 3, 0];
+
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: BufferUsages::INDEX,
+        });
+
+        let mesh = Mesh {
+            name: "Mesh".into(),
+            vertex_buffer,
+            index_buffer,
+            num_indices: 6,
+            material: 0,
+        };
+
+        let material = Material::new(
+            device,
+            "Sprite Atlas",
+            atlas.texture,
+            texture_bind_group_layout,
+        );
+        let model = Model {
+            meshes: vec![mesh],
+            materials: vec![material],
+        };
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[texture_bind_group_layout, &camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline =
+            create_render_pipeline(device, &render_pipeline_layout, format, sample_count);
+
+        Ok(Self {
+            render_pipeline,
+            render_pipeline_layout,
+            format,
+            target_size,
+            sample_count,
+            msaa_target: create_msaa_target(device, format, target_size, sample_count),
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            camera_bind_group_layout,
+            model,
+            sprite_batch: SpriteBatch::new(device, 1024),
+            sprite_regions,
+            tileset_regions,
+            clear_color: Color {
+                r: 0.2,
+                g: 0.2,
+                b: 0.0,
+                a: 1.0,
+            },
+        })
+    }
+
+    /// Switches to drawing with `sample_count` samples per pixel.
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32) {
+        if sample_count == self.sample_count {
+            return;
+        }
+
+        self.sample_count = sample_count;
+        self.render_pipeline = create_render_pipeline(
+            device,
+            &self.render_pipeline_layout,
+            self.format,
+            sample_count,
+        );
+        self.msaa_target = create_msaa_target(device, self.format, self.target_size, sample_count);
+    }
+
+    /// Uploads everything needed to draw `world` through `camera`, with the
+    /// player `alpha` of the way from its previous tick to its current one.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        world: &World,
+        camera: &Camera,
+        alpha: f32,
+    ) {
+        self.camera_uniform = CameraUniform::new(camera);
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+
+        self.queue_sprites(world, alpha);
+        self.sprite_batch.prepare(device, queue);
+    }
+
+    /// Draws what was last prepared into `view`.
+    pub fn render(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: self.msaa_target.as_ref().unwrap_or(view),
+                resolve_target: self.msaa_target.as_ref().map(|_| view),
+                ops: Operations {
+                    load: LoadOp::Clear(self.clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_vertex_buffer(1, self.sprite_batch.instance_buffer().slice(..));
+        render_pass.set_pipeline(&self.render_pipeline);
+        for batch in self.sprite_batch.batches() {
+            render_pass.draw_model_instanced_with_material(
+                &self.model,
+                &self.model.materials[batch.material],
+                batch.instances.clone(),
+                &self.camera_bind_group,
+            );
+        }
+    }
+
+    /// Fills the sprite batch with everything that should be drawn this
+    /// frame.
+    fn queue_sprites(&mut self, world: &World, alpha: f32) {
+        self.sprite_batch.clear();
+
+        let level = &world.level;
+        let map = &level.map;
+        for (layer_index, layer) in map.layers.iter().enumerate() {
+            for (index, tile) in layer.tiles.iter().enumerate() {
+                let Some(tileset) = level.tileset_for(tile.id) else {
+                    continue;
+                };
+
+                let index = index as u32;
+                let position = Vec2::new((index % map.width) as f32, (index / map.width) as f32)
+                    * map.tile_size;
+
+                self.sprite_batch.push(Sprite {
+                    instance: Instance {
+                        position,
+                        rotation: 0.,
+                        scale: map.tile_size,
+                        tint: Vec4::ONE,
+                        uv_rect: self.tileset_regions[tileset]
+                            .sub_rect(level.tilesets[tileset].uv_rect(tile.id)),
+                    },
+                    material: 0,
+                    layer: layer_index as i32,
+                });
+            }
+        }
+
+        // Platforms are drawn as a row of their tile, or a block of them for
+        // taller platforms
+        let platform_layer = map.layers.len() as i32;
+        for platform in &world.platforms {
+            let Some((tile, tileset)) = platform
+                .tile
+                .and_then(|tile| Some((tile, level.tileset_for(tile)?)))
+            else {
+                continue;
+            };
+
+            let position = platform.previous_position.lerp(platform.position, alpha);
+            let uv_rect =
+                self.tileset_regions[tileset].sub_rect(level.tilesets[tileset].uv_rect(tile));
+            let tiles = (platform.size / map.tile_size).ceil().as_uvec2();
+            for y in 0..tiles.y {
+                for x in 0..tiles.x {
+                    self.sprite_batch.push(Sprite {
+                        instance: Instance {
+                            position: position + Vec2::new(x as f32, y as f32) * map.tile_size,
+                            rotation: 0.,
+                            scale: map.tile_size,
+                            tint: Vec4::ONE,
+                            uv_rect,
+                        },
+                        material: 0,
+                        layer: platform_layer,
+                    });
+                }
+            }
+        }
+
+        let animator = &world.animator;
+        let uv_rect = self.sprite_regions[&animator.current_frame().region].uv_rect;
+        let mut player = world.instance.lerp(&world.previous_instance, alpha);
+        player.uv_rect = if animator.flip_x {
+            // Sampling from the right edge with a negative width mirrors the
+            // sprite without moving it
+            Vec4::new(uv_rect.x + uv_rect.z, uv_rect.y, -uv_rect.z, uv_rect.w)
+        } else {
+            uv_rect
+        };
+
+        self.sprite_batch.push(Sprite {
+            instance: player,
+            material: 0,
+            layer: platform_layer + 1,
+        });
+    }
+}
+
+fn create_render_pipeline(
+    device: &Device,
+    layout: &PipelineLayout,
+    format: TextureFormat,
+    sample_count: u32,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Sprites can be mirrored with a negative scale, which flips
+            // their winding, so nothing is culled
+            cull_mode: None,
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+/// The multisampled texture to draw to, if there's more than one sample per
+/// pixel.
+fn create_msaa_target(
+    device: &Device,
+    format: TextureFormat,
+    size: UVec2,
+    sample_count: u32,
+) -> Option<TextureView> {
+    if sample_count <= 1 {
+        return None;
+    }
+
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("MSAA Target"),
+        size: Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    Some(texture.create_view(&TextureViewDescriptor::default()))
+}
@@ -0,0 +1,314 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    input::{ActionSet, Axis, InputFrame},
+    resources,
+    world::World,
+};
+
+const MAGIC: &[u8; 4] = b"P4RP";
+const VERSION: u8 = 1;
+
+/// The most frames a replay can hold, a day's worth at 60 ticks a second.
+/// Each run of frames takes a few bytes but can stand for thousands of them,
+/// so without a limit a tiny corrupt file could ask for gigabytes.
+const MAX_FRAMES: usize = 60 * 60 * 60 * 24;
+
+/// Every tick's input from a play session, along with everything needed to
+/// play it back exactly: the level, the random seed and the tick rate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    /// The level, relative to the resources directory.
+    pub level: String,
+    pub seed: u64,
+    pub tick_rate: u32,
+    pub frames: Vec<InputFrame>,
+    /// The world's checksum after the last frame.
+    pub checksum: u64,
+}
+
+impl Replay {
+    pub fn new(level: &str, seed: u64, tick_rate: u32) -> Self {
+        Self {
+            level: level.into(),
+            seed,
+            tick_rate,
+            frames: Vec::new(),
+            checksum: 0,
+        }
+    }
+
+    /// Steps `world` through every recorded frame.
+    pub fn play(&self, world: &mut World) {
+        let dt = 1. / self.tick_rate as f32;
+        for frame in &self.frames {
+            world.step(*frame, dt);
+        }
+    }
+
+    /// Packs the replay into a compact binary format. Input rarely changes
+    /// from one tick to the next, so frames are stored as runs of identical
+    /// ones.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut runs: Vec<(u16, InputFrame)> = Vec::new();
+        for frame in &self.frames {
+            match runs.last_mut() {
+                Some((count, last)) if last == frame && *count < u16::MAX => *count += 1,
+                _ => runs.push((1, *frame)),
+            }
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend(self.seed.to_le_bytes());
+        bytes.extend(self.tick_rate.to_le_bytes());
+        bytes.extend((self.level.len() as u16).to_le_bytes());
+        bytes.extend(self.level.as_bytes());
+        bytes.extend(self.checksum.to_le_bytes());
+        bytes.extend((runs.len() as u32).to_le_bytes());
+        for (count, frame) in runs {
+            bytes.extend(count.to_le_bytes());
+            bytes.extend(frame.held.bits().to_le_bytes());
+            bytes.extend(frame.axes.map(|axis| axis as u8));
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+        if take::<4>(&mut bytes)? != *MAGIC {
+            bail!("not a replay file");
+        }
+        let version = take::<1>(&mut bytes)?[0];
+        if version != VERSION {
+            bail!("unsupported replay version {}", version);
+        }
+
+        let seed = u64::from_le_bytes(take(&mut bytes)?);
+        let tick_rate = u32::from_le_bytes(take(&mut bytes)?);
+        let level_len = u16::from_le_bytes(take(&mut bytes)?) as usize;
+        if bytes.len() < level_len {
+            bail!("replay ends in the middle of its level name");
+        }
+        let (level, rest) = bytes.split_at(level_len);
+        let level = String::from_utf8(level.to_vec()).context("replay level name isn't UTF-8")?;
+        bytes = rest;
+
+        let checksum = u64::from_le_bytes(take(&mut bytes)?);
+        let runs = u32::from_le_bytes(take(&mut bytes)?);
+
+        let mut frames = Vec::new();
+        for _ in 0..runs {
+            let count = u16::from_le_bytes(take(&mut bytes)?);
+            let held = ActionSet::from_bits(u16::from_le_bytes(take(&mut bytes)?));
+            let axes = take::<{ Axis::ALL.len() }>(&mut bytes)?.map(|axis| axis as i8);
+            if frames.len() + count as usize > MAX_FRAMES {
+                bail!("replay is longer than the limit of {} frames", MAX_FRAMES);
+            }
+            frames.resize(frames.len() + count as usize, InputFrame { held, axes });
+        }
+
+        if !bytes.is_empty() {
+            bail!("{} unexpected bytes after the last frame", bytes.len());
+        }
+
+        Ok(Self {
+            level,
+            seed,
+            tick_rate,
+            frames,
+            checksum,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())
+            .with_context(|| format!("failed to save replay to {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("failed to read replay from {}", path.display()))?;
+        Self::from_bytes(&bytes)
+            .with_context(|| format!("failed to parse replay {}", path.display()))
+    }
+}
+
+/// Records a play session to a file.
+pub struct Recorder {
+    pub path: PathBuf,
+    pub replay: Replay,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, replay: Replay) -> Self {
+        Self { path, replay }
+    }
+
+    pub fn record(&mut self, frame: InputFrame) {
+        self.replay.frames.push(frame);
+    }
+
+    /// Writes everything recorded so far, stamped with `world`'s checksum.
+    pub fn save(&mut self, world: &World) -> Result<()> {
+        self.replay.checksum = world.checksum();
+        self.replay.save(&self.path)?;
+
+        tracing::info!(
+            path = %self.path.display(),
+            ticks = self.replay.frames.len(),
+            checksum = format_args!("{:016x}", self.replay.checksum),
+            "saved replay"
+        );
+        Ok(())
+    }
+}
+
+/// Plays the replay at `path` back without a window and checks that the
+/// world ends up exactly where it did when it was recorded. Returns the
+/// final checksum.
+pub async fn verify(path: &Path) -> Result<u64> {
+    let replay = Replay::load(path)?;
+    let level = resources::load_level(&replay.level).await?;
+
+    let mut world = World::new(level, replay.seed);
+    replay.play(&mut world);
+
+    let checksum = world.checksum();
+    if checksum != replay.checksum {
+        bail!(
+            "replay {} diverged after {} ticks: expected checksum {:016x}, got {:016x}",
+            path.display(),
+            replay.frames.len(),
+            replay.checksum,
+            checksum
+        );
+    }
+
+    Ok(checksum)
+}
+
+/// Takes the next `N` bytes off the front of `bytes`.
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N]> {
+    if bytes.len() < N {
+        bail!("replay ends unexpectedly");
+    }
+
+    let (taken, rest) = bytes.split_at(N);
+    *bytes = rest;
+    Ok(taken.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input::Action, state::LEVEL, timestep::FixedTimestep};
+
+    /// Records `ticks` ticks of running back and forth and jumping.
+    async fn record(ticks: usize) -> Replay {
+        let tick_rate = FixedTimestep::default().tick_rate;
+        let mut replay = Replay::new(LEVEL, 42, tick_rate);
+        let mut world = World::new(resources::load_level(LEVEL).await.unwrap(), replay.seed);
+
+        for tick in 0..ticks {
+            let (action, x) = if tick % 240 < 120 {
+                (Action::MoveRight, 1.)
+            } else {
+                (Action::MoveLeft, -1.)
+            };
+            let mut held = ActionSet::from_iter([action]);
+            if tick % 45 < 10 {
+                held.insert(Action::Jump);
+            }
+            let mut frame = InputFrame {
+                held,
+                ..Default::default()
+            };
+            frame.set_axis(Axis::MoveX, x);
+
+            replay.frames.push(frame);
+            world.step(frame, 1. / tick_rate as f32);
+        }
+
+        replay.checksum = world.checksum();
+        replay
+    }
+
+    #[tokio::test]
+    async fn bytes_round_trip() {
+        let replay = record(600).await;
+        let bytes = replay.to_bytes();
+
+        assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+        // Runs of identical frames are stored once
+        assert!(bytes.len() < replay.frames.len());
+    }
+
+    #[tokio::test]
+    async fn playing_back_reproduces_the_checksum() {
+        let replay = record(600).await;
+        let replay = Replay::from_bytes(&replay.to_bytes()).unwrap();
+
+        let level = resources::load_level(&replay.level).await.unwrap();
+        let mut world = World::new(level, replay.seed);
+        replay.play(&mut world);
+        assert_eq!(world.checksum(), replay.checksum);
+    }
+
+    #[tokio::test]
+    async fn verify_catches_divergence() {
+        let path = std::env::temp_dir().join(format!("plat4rs-replay-{}", std::process::id()));
+        let mut replay = record(300).await;
+
+        replay.save(&path).unwrap();
+        assert_eq!(verify(&path).await.unwrap(), replay.checksum);
+
+        replay.checksum ^= 1;
+        replay.save(&path).unwrap();
+        let error = verify(&path).await.unwrap_err();
+        assert!(error.to_string().contains("diverged"), "{}", error);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn from_bytes_rejects_truncated_files() {
+        let replay = Replay {
+            frames: vec![InputFrame::default(); 3],
+            ..Replay::new(LEVEL, 1, 60)
+        };
+        let bytes = replay.to_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(Replay::from_bytes(&bytes[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn from_bytes_rejects_too_many_frames() {
+        let runs = MAX_FRAMES / u16::MAX as usize + 1;
+
+        let mut bytes = Vec::new();
+        bytes.extend(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend(60u32.to_le_bytes());
+        bytes.extend(0u16.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend((runs as u32).to_le_bytes());
+        for _ in 0..runs {
+            bytes.extend(u16::MAX.to_le_bytes());
+            bytes.extend(0u16.to_le_bytes());
+            bytes.extend([0; Axis::ALL.len()]);
+        }
+
+        let error = Replay::from_bytes(&bytes).unwrap_err();
+        assert!(error.to_string().contains("limit"), "{}", error);
+    }
+}
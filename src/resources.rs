@@ -1,18 +1,23 @@
-use std::{fs::read_to_string, path::Path};
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use wgpu::{Device, Queue};
 
-use crate::texture;
+use crate::{level::Level, texture, tiled};
+
+/// build.rs copies the `resources` directory next to the build artifacts.
+fn resource_path(file_name: &str) -> PathBuf {
+    Path::new(env!("OUT_DIR")).join("resources").join(file_name)
+}
 
 pub async fn load_string(file_name: &str) -> Result<String> {
-    let path = Path::new(env!("OUT_DIR")).join("res").join(file_name);
+    let path = resource_path(file_name);
     let txt = std::fs::read_to_string(path)?;
     Ok(txt)
 }
 
 pub async fn load_binary(file_name: &str) -> Result<Vec<u8>> {
-    let path = Path::new(env!("OUT_DIR")).join("res").join(file_name);
+    let path = resource_path(file_name);
     let txt = std::fs::read(path)?;
     Ok(txt)
 }
@@ -25,3 +30,21 @@ pub async fn load_texture(
     let data = load_binary(file_name).await?;
     texture::Texture::from_bytes(device, queue, &data, file_name)
 }
+
+/// Loads a level made in Tiled, see `tiled::load_level`. Tileset images are
+/// made relative to the resources directory, so they can be loaded with the
+/// other functions here.
+pub async fn load_level(file_name: &str) -> Result<Level> {
+    let mut level = tiled::load_level(&resource_path(file_name))?;
+
+    let level_dir = Path::new(file_name)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    for tileset in &mut level.tilesets {
+        if let Some(image) = &mut tileset.image {
+            *image = level_dir.join(&image).to_string_lossy().into_owned();
+        }
+    }
+
+    Ok(level)
+}
@@ -0,0 +1,231 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use image::RgbaImage;
+use wgpu::{
+    Backends, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Extent3d,
+    ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, MapMode, Origin3d, PowerPreference, Queue,
+    Texture, TextureAspect, TextureFormat, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+use crate::{
+    camera::Camera,
+    gpu::{AdapterAttempt, Gpu},
+    model::Material,
+    renderer::Renderer,
+    texture,
+    world::World,
+};
+
+/// Where screenshots taken in game are saved.
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Only the software adapter, so frames come out the same on every machine.
+pub const SOFTWARE_ADAPTER: [AdapterAttempt; 1] = [AdapterAttempt {
+    backends: Backends::all(),
+    force_fallback_adapter: true,
+}];
+
+/// Reads `texture` back from the GPU. Blocks until everything submitted so
+/// far has finished.
+pub fn capture(device: &Device, queue: &Queue, texture: &Texture) -> Result<RgbaImage> {
+    let format = texture.format();
+    let swap_red_blue = match format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+        _ => bail!("can't capture {:?} textures", format),
+    };
+
+    let width = texture.width();
+    let height = texture.height();
+
+    // Rows in the buffer have to start on 256 byte boundaries, so each one is
+    // padded out
+    let unpadded_bytes_per_row = width * 4;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+        * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Screenshot Buffer"),
+        size: (padded_bytes_per_row * height) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Screenshot Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row.try_into()?),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .context("the GPU never finished the screenshot")?
+        .context("failed to read the screenshot back from the GPU")?;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    for row in slice
+        .get_mapped_range()
+        .chunks(padded_bytes_per_row as usize)
+    {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+    }
+    buffer.unmap();
+
+    if swap_red_blue {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    Ok(RgbaImage::from_raw(width, height, pixels).unwrap())
+}
+
+/// Saves `image` as a PNG in `dir`, named after the time, and returns where.
+pub fn save(image: &RgbaImage, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let path = dir.join(format!(
+        "screenshot-{}-{:03}.png",
+        time.as_secs(),
+        time.subsec_millis()
+    ));
+    image
+        .save(&path)
+        .with_context(|| format!("failed to save screenshot to {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Draws `world` as `camera` sees it without a window, using the first
+/// adapter `attempts` find. With `SOFTWARE_ADAPTER`, the same world always
+/// comes out the same, which makes for golden-image tests.
+pub async fn render_offscreen(
+    world: &World,
+    camera: &Camera,
+    attempts: &[AdapterAttempt],
+) -> Result<RgbaImage> {
+    // # Safety
+    //
+    // There's no window.
+    let gpu = unsafe { Gpu::new(None, attempts, PowerPreference::default()) }.await?;
+    let (device, queue) = gpu.request_device().await?;
+
+    let format = TextureFormat::Rgba8UnormSrgb;
+    let texture_bind_group_layout = Material::bind_group_layout(&device);
+    let mut renderer = Renderer::new(
+        &device,
+        &queue,
+        format,
+        camera.resolution,
+        1,
+        &world.level,
+        &texture_bind_group_layout,
+    )
+    .await?;
+
+    let target = texture::Texture::create_render_target(
+        &device,
+        camera.resolution.x,
+        camera.resolution.y,
+        format,
+        "Offscreen Target",
+    );
+
+    renderer.prepare(&device, &queue, world, camera, 1.);
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Offscreen Encoder"),
+    });
+    renderer.render(&mut encoder, &target.view);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    capture(&device, &queue, &target.texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalSize;
+
+    use super::*;
+    use crate::{game_state::player_camera, run_headless, state::VIRTUAL_RESOLUTION};
+
+    /// The checked-in frame `golden_frame` has to match. Run the test with
+    /// `UPDATE_GOLDEN=1` to overwrite it after an intended change to drawing.
+    const GOLDEN: &str = "tests/golden/level1_tick120.png";
+
+    /// How far a channel can be off before a pixel counts as different, as
+    /// software adapters don't all round the same way.
+    const CHANNEL_TOLERANCE: u8 = 2;
+
+    #[tokio::test]
+    async fn golden_frame() {
+        let world = run_headless(120, 0).await.unwrap();
+        let resolution = VIRTUAL_RESOLUTION;
+        let window_size = PhysicalSize::new(resolution.x, resolution.y);
+        let (camera, _) = player_camera(&world, window_size, resolution);
+        // Failing rather than skipping keeps a missing adapter from passing
+        // the test unnoticed
+        let image = render_offscreen(&world, &camera, &SOFTWARE_ADAPTER)
+            .await
+            .context("the golden image test needs a software adapter, e.g. Mesa's llvmpipe")
+            .unwrap();
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(GOLDEN);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            image.save(&path).unwrap();
+            return;
+        }
+
+        let golden = image::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(image.dimensions(), golden.dimensions());
+
+        let different = image
+            .pixels()
+            .zip(golden.pixels())
+            .filter(|(a, b)| {
+                a.0.iter()
+                    .zip(b.0.iter())
+                    .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        assert_eq!(different, 0, "{} pixels differ from {}", different, GOLDEN);
+    }
+}
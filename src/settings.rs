@@ -0,0 +1,241 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use wgpu::{Backends, PresentMode};
+use winit::{
+    dpi::PhysicalSize,
+    monitor::MonitorHandle,
+    window::{Fullscreen, Window},
+};
+
+use crate::{config::ConfigFile, gpu::AdapterAttempt};
+
+/// Where the player's settings are kept.
+pub const SETTINGS_PATH: &str = "settings.toml";
+
+/// How frames are handed to the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Vsync {
+    /// Wait for the display, never tearing. Supported everywhere.
+    Fifo,
+    /// Wait for the display, but replace frames still waiting with newer
+    /// ones, for lower latency without tearing.
+    Mailbox,
+    /// Show frames as soon as they're done, tearing if need be.
+    Immediate,
+}
+
+impl Vsync {
+    pub fn present_mode(self) -> PresentMode {
+        match self {
+            Vsync::Fifo => PresentMode::Fifo,
+            Vsync::Mailbox => PresentMode::Mailbox,
+            Vsync::Immediate => PresentMode::Immediate,
+        }
+    }
+}
+
+/// Which graphics API to draw with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// Whichever works, trying the modern APIs first.
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl Backend {
+    /// The ways of finding an adapter on this backend, in order.
+    pub fn adapter_attempts(self) -> Vec<AdapterAttempt> {
+        let backends = match self {
+            Backend::Auto => return AdapterAttempt::DEFAULT.to_vec(),
+            Backend::Vulkan => Backends::VULKAN,
+            Backend::Metal => Backends::METAL,
+            Backend::Dx12 => Backends::DX12,
+            Backend::Dx11 => Backends::DX11,
+            Backend::Gl => Backends::GL,
+        };
+
+        [false, true]
+            .map(|force_fallback_adapter| AdapterAttempt {
+                backends,
+                force_fallback_adapter,
+            })
+            .to_vec()
+    }
+}
+
+/// Which GPU to prefer when there's more than one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerPreference {
+    LowPower,
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> Self {
+        match preference {
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    Windowed,
+    /// A window covering the whole monitor.
+    Borderless,
+    /// Exclusive fullscreen, at the monitor's largest video mode.
+    Fullscreen,
+}
+
+impl WindowMode {
+    /// What to pass to winit to put a window on `monitor` into this mode.
+    pub fn fullscreen(self, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+        match self {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::Fullscreen => {
+                let video_mode = monitor.as_ref().and_then(|monitor| {
+                    monitor.video_modes().max_by_key(|mode| {
+                        let size = mode.size();
+                        (size.width * size.height, mode.refresh_rate_millihertz())
+                    })
+                });
+
+                // Not every platform has video modes to pick from
+                Some(match video_mode {
+                    Some(video_mode) => Fullscreen::Exclusive(video_mode),
+                    None => Fullscreen::Borderless(monitor),
+                })
+            }
+        }
+    }
+}
+
+/// How the game runs on this machine, as opposed to how it plays.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub vsync: Vsync,
+    /// Only read at startup.
+    pub backend: Backend,
+    /// Only read at startup.
+    pub power_preference: PowerPreference,
+    /// The window's size when windowed, in physical pixels.
+    pub window_width: u32,
+    pub window_height: u32,
+    pub window_mode: WindowMode,
+    /// How many samples each pixel is drawn with, to smooth the edges of
+    /// rotated sprites. 1 turns it off.
+    pub msaa_samples: u32,
+}
+
+impl ConfigFile for Settings {
+    const DESCRIPTION: &'static str = "settings";
+
+    fn check(&self) -> Result<()> {
+        self.validate()
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<()> {
+        if self.window_width == 0 || self.window_height == 0 {
+            bail!(
+                "window size {}x{} is empty",
+                self.window_width,
+                self.window_height
+            );
+        }
+        if !matches!(self.msaa_samples, 1 | 2 | 4 | 8) {
+            bail!(
+                "{} MSAA samples isn't one of 1, 2, 4 or 8",
+                self.msaa_samples
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn window_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.window_width, self.window_height)
+    }
+
+    /// Puts `window` into the window mode.
+    pub fn apply_window_mode(&self, window: &Window) {
+        window.set_fullscreen(self.window_mode.fullscreen(window.current_monitor()));
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            vsync: Vsync::Fifo,
+            backend: Backend::Auto,
+            power_preference: PowerPreference::LowPower,
+            window_width: 800,
+            window_height: 600,
+            window_mode: WindowMode::Windowed,
+            msaa_samples: 1,
+        }
+    }
+}
+
+/// Settings given on the command line, which win over the settings file.
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct SettingsOverrides {
+    #[arg(long)]
+    pub vsync: Option<Vsync>,
+    #[arg(long)]
+    pub backend: Option<Backend>,
+    #[arg(long)]
+    pub power_preference: Option<PowerPreference>,
+    /// The window's width when windowed, in physical pixels.
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// The window's height when windowed, in physical pixels.
+    #[arg(long)]
+    pub height: Option<u32>,
+    #[arg(long)]
+    pub window_mode: Option<WindowMode>,
+    /// Samples per pixel: 1, 2, 4 or 8.
+    #[arg(long)]
+    pub msaa: Option<u32>,
+}
+
+impl SettingsOverrides {
+    pub fn apply(&self, settings: &mut Settings) -> Result<()> {
+        if let Some(vsync) = self.vsync {
+            settings.vsync = vsync;
+        }
+        if let Some(backend) = self.backend {
+            settings.backend = backend;
+        }
+        if let Some(power_preference) = self.power_preference {
+            settings.power_preference = power_preference;
+        }
+        if let Some(width) = self.width {
+            settings.window_width = width;
+        }
+        if let Some(height) = self.height {
+            settings.window_height = height;
+        }
+        if let Some(window_mode) = self.window_mode {
+            settings.window_mode = window_mode;
+        }
+        if let Some(msaa) = self.msaa {
+            settings.msaa_samples = msaa;
+        }
+
+        settings.validate()
+    }
+}
@@ -4,12 +4,12 @@ struct Camera {
     view_proj: mat4x4<f32>,
 }
 
-@group(0) @binding(0)
+@group(1) @binding(0)
 var<uniform> camera: Camera;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
 }
 
 struct InstanceInput {
@@ -17,11 +17,14 @@ struct InstanceInput {
     @location(3) model_matrix_1: vec4<f32>,
     @location(4) model_matrix_2: vec4<f32>,
     @location(5) model_matrix_3: vec4<f32>,
+    @location(6) tint: vec4<f32>,
+    @location(7) uv_rect: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) color: vec3<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) tint: vec4<f32>,
 }
 
 @vertex
@@ -38,7 +41,8 @@ fn vs_main(
 
     var out: VertexOutput;
 
-    out.color = vert.color;
+    out.tex_coords = instance.uv_rect.xy + vert.tex_coords * instance.uv_rect.zw;
+    out.tint = instance.tint;
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(vert.position, 1.);
 
     return out;
@@ -46,7 +50,12 @@ fn vs_main(
 
 // Fragment shader
 
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.);
-}
\ No newline at end of file
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.tint;
+}
@@ -0,0 +1,115 @@
+use std::ops::Range;
+
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, Device, Queue};
+
+use crate::instance::{Instance, InstanceRaw};
+
+/// A single quad to draw this frame.
+pub struct Sprite {
+    pub instance: Instance,
+    /// Index into the materials of the model the batch is drawn with.
+    pub material: usize,
+    /// Sprites on lower layers are drawn first.
+    pub layer: i32,
+}
+
+/// A run of instances that share a material and can be drawn with one call.
+pub struct Batch {
+    pub material: usize,
+    pub instances: Range<u32>,
+}
+
+/// Collects every sprite drawn in a frame, sorts them by layer and material
+/// and uploads them into one instance buffer, so a frame is drawn with one
+/// instanced draw call per material switch instead of one per sprite.
+pub struct SpriteBatch {
+    sprites: Vec<Sprite>,
+    instances: Vec<InstanceRaw>,
+    batches: Vec<Batch>,
+    instance_buffer: Buffer,
+    capacity: usize,
+}
+
+impl SpriteBatch {
+    pub fn new(device: &Device, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        Self {
+            sprites: Vec::new(),
+            instances: Vec::new(),
+            batches: Vec::new(),
+            instance_buffer: create_instance_buffer(device, capacity),
+            capacity,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+
+    pub fn push(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    /// Sorts the sprites pushed since the last `clear`, splits them into
+    /// batches and uploads their instances, growing the instance buffer if
+    /// they don't fit.
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) {
+        // A stable sort keeps sprites on the same layer and material in the
+        // order they were pushed
+        self.sprites
+            .sort_by_key(|sprite| (sprite.layer, sprite.material));
+
+        self.instances.clear();
+        self.batches.clear();
+
+        for sprite in &self.sprites {
+            let index = self.instances.len() as u32;
+            self.instances.push(sprite.instance.to_raw());
+
+            match self.batches.last_mut() {
+                Some(batch) if batch.material == sprite.material => batch.instances.end += 1,
+                _ => self.batches.push(Batch {
+                    material: sprite.material,
+                    instances: index..index + 1,
+                }),
+            }
+        }
+
+        if self.instances.len() > self.capacity {
+            self.capacity = self.instances.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(device, self.capacity);
+        }
+
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+    }
+
+    pub fn batches(&self) -> &[Batch] {
+        &self.batches
+    }
+
+    pub fn instance_buffer(&self) -> &Buffer {
+        &self.instance_buffer
+    }
+}
+
+fn create_instance_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("Sprite Instance Buffer"),
+        size: (capacity * std::mem::size_of::<InstanceRaw>()) as u64,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
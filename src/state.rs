@@ -1,23 +1,33 @@
-use std::f32::consts::PI;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use glam::Vec2;
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use anyhow::{Context, Result};
+use glam::UVec2;
 use wgpu::{
-    include_wgsl, Backends, BufferUsages, Color, CommandEncoderDescriptor, Device,
-    DeviceDescriptor, Features, InstanceDescriptor, Limits, LoadOp, Operations, PowerPreference,
-    Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RequestAdapterOptions,
-    Surface, SurfaceConfiguration, SurfaceError, TextureUsages, TextureViewDescriptor,
+    Adapter, CommandEncoderDescriptor, Device, PresentMode, Queue, Surface, SurfaceCapabilities,
+    SurfaceConfiguration, SurfaceError, TextureFormat, TextureFormatFeatureFlags, TextureUsages,
+    TextureViewDescriptor,
 };
 use winit::dpi::PhysicalSize;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::window::Window;
 
-use crate::camera::CameraUniform;
 use crate::game_state::GameState;
-use crate::instance::InstanceRaw;
-use crate::model::{DrawModel, ModelVertex};
-use crate::Vertex;
+use crate::gpu::Gpu;
+use crate::model::Material;
+use crate::renderer::Renderer;
+use crate::resources;
+use crate::screenshot::{self, SCREENSHOT_DIR};
+use crate::settings::{Settings, Vsync, WindowMode};
+use crate::virtual_screen::VirtualScreen;
+use crate::world::World;
+
+/// The size of the playfield everything is drawn at before being scaled up to
+/// the window.
+pub const VIRTUAL_RESOLUTION: UVec2 = UVec2::new(320, 180);
+
+/// The level the game starts in, relative to the resources directory.
+pub const LEVEL: &str = "levels/level1.tmx";
 
 pub struct State {
     pub surface: Surface,
@@ -26,55 +36,34 @@ pub struct State {
     pub config: SurfaceConfiguration,
     pub size: PhysicalSize<u32>,
     pub window: Window,
-    pub render_pipeline: RenderPipeline,
+    pub adapter: Adapter,
+    pub settings: Settings,
+    pub renderer: Renderer,
+    pub virtual_screen: VirtualScreen,
     pub game_state: GameState,
 }
 
 impl State {
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window, settings: Settings) -> Result<Self> {
         let size = window.inner_size();
 
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(InstanceDescriptor {
-            backends: Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
-
         // # Safety
         //
         // The surface needs to live as long as the window that created it.
-        // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
-
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    features: Features::empty(),
-
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    limits: if cfg!(target_arch = "wasm32") {
-                        Limits::downlevel_webgl2_defaults()
-                    } else {
-                        Limits::default()
-                    },
-
-                    label: None, // Trace path
-                },
-                None,
+        // State owns the window and drops it after the surface.
+        let gpu = unsafe {
+            Gpu::new(
+                Some(&window),
+                &settings.backend.adapter_attempts(),
+                settings.power_preference.into(),
             )
-            .await
-            .unwrap();
+        }
+        .await?;
+        let (device, queue) = gpu.request_device().await?;
+        let adapter = gpu.adapter;
+        let surface = gpu
+            .surface
+            .context("the GPU adapter came without a surface")?;
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -85,94 +74,121 @@ impl State {
             .formats
             .iter()
             .copied()
-            .filter(|f| f.describe().srgb)
-            .next()
-            .unwrap_or(surface_caps.formats[0]);
+            .find(|f| f.describe().srgb)
+            .or_else(|| surface_caps.formats.first().copied())
+            .context("the window's surface doesn't support any formats")?;
 
         let config = SurfaceConfiguration {
             usage: TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: present_mode(&surface_caps, settings.vsync),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
 
         surface.configure(&device, &config);
 
-        let game_state = GameState::new(&device, &size);
-
-        let shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&game_state.camera_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+        let texture_bind_group_layout = Material::bind_group_layout(&device);
+
+        let level = resources::load_level(LEVEL).await?;
+        let renderer = Renderer::new(
+            &device,
+            &queue,
+            config.format,
+            VIRTUAL_RESOLUTION,
+            sample_count(&adapter, config.format, settings.msaa_samples),
+            &level,
+            &texture_bind_group_layout,
+        )
+        .await?;
+
+        // Seeding from the clock keeps runs varied, replays record the seed
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+
+        let game_state = GameState::new(&size, VIRTUAL_RESOLUTION, World::new(level, seed));
+
+        let virtual_screen = VirtualScreen::new(
+            &device,
+            VIRTUAL_RESOLUTION,
+            config.format,
+            size,
+            &texture_bind_group_layout,
+        );
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
-
-        Self {
+        Ok(Self {
             surface,
             device,
             queue,
             config,
             size,
             window,
-            render_pipeline,
+            adapter,
+            settings,
+            renderer,
+            virtual_screen,
             game_state,
+        })
+    }
+
+    /// Switches to `settings`, as far as possible without restarting.
+    pub fn apply_settings(&mut self, settings: Settings) {
+        if settings.backend != self.settings.backend
+            || settings.power_preference != self.settings.power_preference
+        {
+            tracing::warn!("the graphics backend and GPU only change on restart");
+        }
+
+        if settings.vsync != self.settings.vsync {
+            let surface_caps = self.surface.get_capabilities(&self.adapter);
+            self.config.present_mode = present_mode(&surface_caps, settings.vsync);
+            self.surface.configure(&self.device, &self.config);
+        }
+
+        if settings.msaa_samples != self.settings.msaa_samples {
+            let sample_count =
+                sample_count(&self.adapter, self.config.format, settings.msaa_samples);
+            self.renderer.set_sample_count(&self.device, sample_count);
+        }
+
+        if settings.window_mode != self.settings.window_mode {
+            settings.apply_window_mode(&self.window);
+        }
+
+        // The window reports its new size once it's been resized, which is
+        // when everything else catches up
+        if settings.window_size() != self.settings.window_size()
+            && settings.window_mode == WindowMode::Windowed
+        {
+            self.window.set_inner_size(settings.window_size());
         }
+
+        self.settings = settings;
     }
 
     pub fn window(&self) -> &Window {
         &self.window
     }
 
+    /// Updates everything that depends on the window's size. Every change to
+    /// the size, including DPI changes, should go through here.
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+        // Minimized windows have no size, and nothing to draw to
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
         }
+
+        self.size = new_size;
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.surface.configure(&self.device, &self.config);
+
+        self.virtual_screen.resize(new_size);
+        self.game_state.resize(new_size);
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
@@ -186,10 +202,25 @@ impl State {
                     },
                 ..
             } => {
-                let _ = match *state {
-                    ElementState::Pressed => self.game_state.pressed_keys.insert(keycode.clone()),
-                    ElementState::Released => self.game_state.pressed_keys.remove(keycode),
-                };
+                if *keycode == VirtualKeyCode::F12 && *state == ElementState::Pressed {
+                    match self.screenshot() {
+                        Ok(path) => tracing::info!(path = %path.display(), "saved screenshot"),
+                        Err(e) => tracing::error!("{:#}", e),
+                    }
+                    return true;
+                }
+
+                if *keycode == VirtualKeyCode::F11 && *state == ElementState::Pressed {
+                    let mut settings = self.settings.clone();
+                    settings.window_mode = match settings.window_mode {
+                        WindowMode::Windowed => WindowMode::Borderless,
+                        _ => WindowMode::Windowed,
+                    };
+                    self.apply_settings(settings);
+                    return true;
+                }
+
+                self.game_state.keyboard.handle_key(*keycode, *state);
 
                 false
             }
@@ -197,22 +228,25 @@ impl State {
         }
     }
 
-    pub fn update(&mut self) {
-        let camera_uniform =
-            CameraUniform::new(&self.game_state.camera, &self.window().inner_size());
-        self.queue.write_buffer(
-            &self.game_state.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[camera_uniform]),
-        );
+    /// Saves the last frame drawn, at the virtual resolution, to the
+    /// screenshot directory and returns where.
+    pub fn screenshot(&self) -> Result<PathBuf> {
+        let image = screenshot::capture(
+            &self.device,
+            &self.queue,
+            &self.virtual_screen.target().texture,
+        )?;
+        screenshot::save(&image, Path::new(SCREENSHOT_DIR))
+    }
 
-        self.queue.write_buffer(
-            &self.game_state.instance_buffer,
-            0,
-            bytemuck::cast_slice(&[self.game_state.instance.to_raw()]),
+    pub fn update(&mut self) {
+        self.renderer.prepare(
+            &self.device,
+            &self.queue,
+            &self.game_state.world,
+            &self.game_state.view_camera,
+            self.game_state.timestep.alpha(),
         );
-
-        self.game_state.last_update = Instant::now();
     }
 
     pub fn render(&mut self) -> Result<(), SurfaceError> {
@@ -228,29 +262,9 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(Color {
-                            r: 0.2,
-                            g: 0.2,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-
-            render_pass.set_vertex_buffer(1, self.game_state.instance_buffer.slice(..));
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.draw_model(&self.game_state.model, &self.game_state.camera_bind_group);
-        }
+        self.renderer
+            .render(&mut encoder, &self.virtual_screen.target().view);
+        self.virtual_screen.draw(&mut encoder, &view);
 
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -259,3 +273,30 @@ impl State {
         Ok(())
     }
 }
+
+/// The present mode for `vsync`, or plain vsync if the surface doesn't
+/// support it.
+fn present_mode(surface_caps: &SurfaceCapabilities, vsync: Vsync) -> PresentMode {
+    let present_mode = vsync.present_mode();
+    if surface_caps.present_modes.contains(&present_mode) {
+        present_mode
+    } else {
+        tracing::warn!("{:?} isn't supported, falling back to Fifo", vsync);
+        PresentMode::Fifo
+    }
+}
+
+/// `requested` samples per pixel, or one if the adapter can't draw to `format`
+/// with that many.
+fn sample_count(adapter: &Adapter, format: TextureFormat, requested: u32) -> u32 {
+    let flags = adapter.get_texture_format_features(format).flags;
+    if requested == 1
+        || flags.sample_count_supported(requested)
+            && flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+    {
+        requested
+    } else {
+        tracing::warn!("{}x MSAA isn't supported, turning it off", requested);
+        1
+    }
+}
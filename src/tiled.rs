@@ -0,0 +1,815 @@
+//! Loading of levels made in the Tiled map editor, from either TMX (XML) or
+//! TMJ (JSON) files. Both formats are first read into the same `Raw*` structs,
+//! which mirror Tiled's JSON format, and then turned into a `Level`.
+//!
+//! Tiles get their gameplay flags from bool properties named `solid`,
+//! `one_way` and `hazard`, set either on the tile in its tileset or on a whole
+//! tile layer. Slopes are set with a `slope` string property of `45`, `22_low`
+//! or `22_high`, rising to the right unless `slope_left` is set and standing on
+//! the floor unless `slope_ceiling` is.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use glam::Vec2;
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    level::{Level, LevelObject, Properties, PropertyValue, Tileset},
+    tilemap::{Tile, TileFlags, TileLayer, TileMap},
+};
+
+/// The top bits of a gid store whether the tile is flipped or rotated, which
+/// the renderer can't draw.
+const GID_FLAGS_MASK: u32 = 0xF000_0000;
+
+/// Loads a `.tmx`, `.tmj` or `.json` map. External tilesets are resolved
+/// relative to the map file.
+pub fn load_level(path: &Path) -> Result<Level> {
+    let raw = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tmx") => {
+            let source = read(path)?;
+            parse_tmx(&source).with_context(|| format!("failed to parse {}", path.display()))?
+        }
+        Some("tmj") | Some("json") => {
+            let source = read(path)?;
+            serde_json::from_str(&source)
+                .with_context(|| format!("failed to parse {}", path.display()))?
+        }
+        _ => bail!("{} is not a Tiled map (.tmx or .tmj)", path.display()),
+    };
+
+    build_level(raw, path)
+}
+
+#[derive(Deserialize)]
+struct RawMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    layers: Vec<RawLayer>,
+    #[serde(default)]
+    tilesets: Vec<RawTileset>,
+    #[serde(default)]
+    properties: Vec<RawProperty>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RawLayer {
+    TileLayer(RawTileLayer),
+    ObjectGroup(RawObjectGroup),
+    Group(RawGroup),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct RawTileLayer {
+    name: String,
+    data: Option<RawData>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    #[serde(default)]
+    properties: Vec<RawProperty>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawData {
+    Gids(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Deserialize)]
+struct RawObjectGroup {
+    name: String,
+    #[serde(default)]
+    objects: Vec<RawObject>,
+}
+
+#[derive(Deserialize)]
+struct RawGroup {
+    name: String,
+    #[serde(default)]
+    layers: Vec<RawLayer>,
+}
+
+#[derive(Deserialize)]
+struct RawObject {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    // Tiled 1.9 briefly called this `class` in JSON maps
+    #[serde(default, rename = "type")]
+    ty: String,
+    #[serde(default)]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    polyline: Option<Vec<RawPoint>>,
+    #[serde(default)]
+    properties: Vec<RawProperty>,
+}
+
+#[derive(Deserialize)]
+struct RawPoint {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Deserialize)]
+struct RawProperty {
+    name: String,
+    #[serde(default = "default_property_type", rename = "type")]
+    ty: String,
+    value: Value,
+}
+
+fn default_property_type() -> String {
+    "string".into()
+}
+
+#[derive(Deserialize)]
+struct RawTileset {
+    // Only present on tilesets embedded in, or referenced from, a map
+    #[serde(default)]
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tilecount: u32,
+    image: Option<String>,
+    #[serde(default)]
+    tiles: Vec<RawTile>,
+}
+
+#[derive(Deserialize)]
+struct RawTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<RawProperty>,
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+fn build_level(raw: RawMap, path: &Path) -> Result<Level> {
+    if !raw.orientation.is_empty() && raw.orientation != "orthogonal" {
+        bail!(
+            "{} uses {} orientation, only orthogonal maps are supported",
+            path.display(),
+            raw.orientation
+        );
+    }
+
+    if raw.infinite {
+        bail!(
+            "{} is an infinite map, which isn't supported",
+            path.display()
+        );
+    }
+
+    if raw.tilewidth != raw.tileheight {
+        bail!(
+            "{} has {}x{} tiles, only square tiles are supported",
+            path.display(),
+            raw.tilewidth,
+            raw.tileheight
+        );
+    }
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut tilesets = Vec::new();
+    let mut tile_flags = HashMap::new();
+    for raw_tileset in raw.tilesets {
+        let first_gid = raw_tileset.firstgid;
+        // Images are relative to the tileset they're used by, but it's more
+        // useful to have them relative to the map
+        let (raw_tileset, tileset_dir) = match &raw_tileset.source {
+            Some(source) => (
+                load_tileset(&base_dir.join(source))
+                    .with_context(|| format!("failed to load tileset for {}", path.display()))?,
+                Path::new(source)
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .to_owned(),
+            ),
+            None => (raw_tileset, PathBuf::new()),
+        };
+
+        for tile in &raw_tileset.tiles {
+            let properties = convert_properties(&tile.properties);
+            tile_flags.insert(first_gid + tile.id, flags_from_properties(&properties));
+        }
+
+        tilesets.push(Tileset {
+            first_gid,
+            name: raw_tileset.name,
+            image: raw_tileset
+                .image
+                .map(|image| tileset_dir.join(image).to_string_lossy().into_owned()),
+            columns: raw_tileset.columns,
+            tile_count: raw_tileset.tilecount,
+        });
+    }
+
+    let mut builder = LevelBuilder {
+        path,
+        map: TileMap::new(raw.width, raw.height, raw.tilewidth as f32),
+        objects: Vec::new(),
+        tile_flags,
+    };
+
+    for layer in raw.layers {
+        builder.add_layer(layer, "")?;
+    }
+
+    Ok(Level {
+        map: builder.map,
+        tilesets,
+        objects: builder.objects,
+        properties: convert_properties(&raw.properties),
+    })
+}
+
+struct LevelBuilder<'a> {
+    path: &'a Path,
+    map: TileMap,
+    objects: Vec<LevelObject>,
+    tile_flags: HashMap<u32, TileFlags>,
+}
+
+impl<'a> LevelBuilder<'a> {
+    /// Adds a layer, flattening groups. `prefix` is the path of the groups the
+    /// layer is nested in, so errors can point at the right layer.
+    fn add_layer(&mut self, layer: RawLayer, prefix: &str) -> Result<()> {
+        match layer {
+            RawLayer::TileLayer(layer) => {
+                let name = format!("{}{}", prefix, layer.name);
+                let tiles = self.tiles(&layer).with_context(|| {
+                    format!("invalid layer '{}' in {}", name, self.path.display())
+                })?;
+                self.map.add_layer(TileLayer { name, tiles });
+            }
+            RawLayer::ObjectGroup(group) => {
+                let name = format!("{}{}", prefix, group.name);
+                for object in group.objects {
+                    self.objects.push(convert_object(object, &name));
+                }
+            }
+            RawLayer::Group(group) => {
+                let prefix = format!("{}{}/", prefix, group.name);
+                for layer in group.layers {
+                    self.add_layer(layer, &prefix)?;
+                }
+            }
+            RawLayer::Other => {}
+        }
+
+        Ok(())
+    }
+
+    fn tiles(&self, layer: &RawTileLayer) -> Result<Vec<Tile>> {
+        let gids = match &layer.data {
+            Some(RawData::Gids(gids)) => gids.clone(),
+            Some(RawData::Encoded(data)) => match layer.encoding.as_deref() {
+                Some("base64") => decode_base64(data, layer.compression.as_deref())?,
+                Some("csv") => parse_csv(data)?,
+                encoding => bail!("unsupported tile data encoding {:?}", encoding),
+            },
+            None => bail!("layer has no tile data"),
+        };
+
+        let expected = (self.map.width * self.map.height) as usize;
+        if gids.len() != expected {
+            bail!("layer has {} tiles, expected {}", gids.len(), expected);
+        }
+
+        let layer_flags = flags_from_properties(&convert_properties(&layer.properties));
+
+        gids.into_iter()
+            .enumerate()
+            .map(|(index, gid)| {
+                if gid & GID_FLAGS_MASK != 0 {
+                    bail!(
+                        "tile {} at column {}, row {} is flipped or rotated, which isn't supported",
+                        gid & !GID_FLAGS_MASK,
+                        index as u32 % self.map.width,
+                        index as u32 / self.map.width
+                    );
+                }
+
+                Ok(match gid {
+                    0 => Tile::EMPTY,
+                    id => Tile {
+                        id,
+                        flags: layer_flags | self.tile_flags.get(&id).copied().unwrap_or_default(),
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+fn load_tileset(path: &Path) -> Result<RawTileset> {
+    let source = read(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tsx") => {
+            let document = Document::parse(&source)
+                .with_context(|| format!("failed to parse {}", path.display()))?;
+            parse_tmx_tileset(document.root_element())
+        }
+        Some("tsj") | Some("json") => serde_json::from_str(&source).map_err(anyhow::Error::from),
+        _ => bail!("{} is not a Tiled tileset (.tsx or .tsj)", path.display()),
+    }
+    .with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn convert_object(object: RawObject, layer: &str) -> LevelObject {
+    let position = Vec2::new(object.x, object.y);
+
+    LevelObject {
+        id: object.id,
+        name: object.name,
+        class: if object.class.is_empty() {
+            object.ty
+        } else {
+            object.class
+        },
+        layer: layer.into(),
+        position,
+        size: Vec2::new(object.width, object.height),
+        polyline: object.polyline.map(|points| {
+            points
+                .iter()
+                .map(|point| position + Vec2::new(point.x, point.y))
+                .collect()
+        }),
+        properties: convert_properties(&object.properties),
+    }
+}
+
+fn convert_properties(raw: &[RawProperty]) -> Properties {
+    raw.iter()
+        .map(|property| {
+            let value = match (property.ty.as_str(), &property.value) {
+                ("bool", Value::Bool(value)) => PropertyValue::Bool(*value),
+                ("int" | "object", Value::Number(value)) if value.is_i64() => {
+                    PropertyValue::Int(value.as_i64().unwrap_or_default())
+                }
+                ("float" | "int", Value::Number(value)) => {
+                    PropertyValue::Float(value.as_f64().unwrap_or_default())
+                }
+                (_, Value::String(value)) => PropertyValue::String(value.clone()),
+                (_, value) => PropertyValue::String(value.to_string()),
+            };
+
+            (property.name.clone(), value)
+        })
+        .collect()
+}
+
+fn flags_from_properties(properties: &Properties) -> TileFlags {
+    let mut flags = TileFlags::EMPTY;
+    let is_set = |name| properties.get(name).and_then(PropertyValue::as_bool) == Some(true);
+
+    if is_set("solid") {
+        flags |= TileFlags::SOLID;
+    }
+    if is_set("one_way") {
+        flags |= TileFlags::ONE_WAY;
+    }
+    if is_set("hazard") {
+        flags |= TileFlags::HAZARD;
+    }
+
+    if let Some(slope) = properties.get("slope").and_then(PropertyValue::as_str) {
+        match slope {
+            "45" => flags |= TileFlags::SLOPE,
+            "22_low" => flags |= TileFlags::HALF_SLOPE_LOW,
+            "22_high" => flags |= TileFlags::HALF_SLOPE_HIGH,
+            _ => tracing::warn!("unknown slope '{}', expected 45, 22_low or 22_high", slope),
+        }
+        if is_set("slope_left") {
+            flags |= TileFlags::SLOPE_LEFT;
+        }
+        if is_set("slope_ceiling") {
+            flags |= TileFlags::SLOPE_CEILING;
+        }
+    }
+
+    flags
+}
+
+fn decode_base64(data: &str, compression: Option<&str>) -> Result<Vec<u32>> {
+    if let Some(compression) = compression.filter(|c| !c.is_empty()) {
+        bail!(
+            "{} compressed tile data isn't supported, save the map with CSV or uncompressed Base64",
+            compression
+        );
+    }
+
+    let bytes = STANDARD
+        .decode(data.trim())
+        .context("invalid Base64 tile data")?;
+
+    if bytes.len() % 4 != 0 {
+        bail!("Base64 tile data isn't a whole number of tiles");
+    }
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+        .collect())
+}
+
+fn parse_csv(data: &str) -> Result<Vec<u32>> {
+    data.split(',')
+        .map(str::trim)
+        .filter(|gid| !gid.is_empty())
+        .map(|gid| {
+            gid.parse()
+                .with_context(|| format!("invalid tile id '{}'", gid))
+        })
+        .collect()
+}
+
+fn parse_tmx(source: &str) -> Result<RawMap> {
+    let document = Document::parse(source)?;
+    let map = document.root_element();
+    if !map.has_tag_name("map") {
+        bail!(
+            "root element is <{}>, expected <map>",
+            map.tag_name().name()
+        );
+    }
+
+    let mut layers = Vec::new();
+    let mut tilesets = Vec::new();
+    let mut properties = Vec::new();
+
+    for child in map.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "tileset" => tilesets.push(match child.attribute("source") {
+                Some(source) => RawTileset {
+                    firstgid: attribute(child, "firstgid")?,
+                    source: Some(source.into()),
+                    name: String::new(),
+                    columns: 0,
+                    tilecount: 0,
+                    image: None,
+                    tiles: Vec::new(),
+                },
+                None => parse_tmx_tileset(child)?,
+            }),
+            "properties" => properties = parse_tmx_properties(child)?,
+            _ => {
+                if let Some(layer) = parse_tmx_layer(child)? {
+                    layers.push(layer);
+                }
+            }
+        }
+    }
+
+    Ok(RawMap {
+        width: attribute(map, "width")?,
+        height: attribute(map, "height")?,
+        tilewidth: attribute(map, "tilewidth")?,
+        tileheight: attribute(map, "tileheight")?,
+        orientation: map.attribute("orientation").unwrap_or_default().into(),
+        infinite: map.attribute("infinite") == Some("1"),
+        layers,
+        tilesets,
+        properties,
+    })
+}
+
+fn parse_tmx_layer(node: Node) -> Result<Option<RawLayer>> {
+    let name: String = node.attribute("name").unwrap_or_default().into();
+    let properties = || -> Result<Vec<RawProperty>> {
+        match child(node, "properties") {
+            Some(properties) => parse_tmx_properties(properties),
+            None => Ok(Vec::new()),
+        }
+    };
+
+    let layer = match node.tag_name().name() {
+        "layer" => {
+            let data =
+                child(node, "data").ok_or_else(|| anyhow!("layer '{}' has no <data>", name))?;
+            let encoding = data.attribute("encoding").map(String::from);
+
+            let data = match encoding {
+                // Without an encoding every tile is its own element
+                None => RawData::Gids(
+                    data.children()
+                        .filter(|tile| tile.has_tag_name("tile"))
+                        .map(|tile| optional_attribute(tile, "gid").map(Option::unwrap_or_default))
+                        .collect::<Result<_>>()
+                        .with_context(|| format!("invalid layer '{}'", name))?,
+                ),
+                Some(_) => RawData::Encoded(data.text().unwrap_or_default().into()),
+            };
+
+            RawLayer::TileLayer(RawTileLayer {
+                data: Some(data),
+                encoding,
+                compression: child(node, "data")
+                    .and_then(|data| data.attribute("compression"))
+                    .map(String::from),
+                properties: properties()?,
+                name,
+            })
+        }
+        "objectgroup" => RawLayer::ObjectGroup(RawObjectGroup {
+            objects: node
+                .children()
+                .filter(|object| object.has_tag_name("object"))
+                .map(parse_tmx_object)
+                .collect::<Result<_>>()
+                .with_context(|| format!("invalid object layer '{}'", name))?,
+            name,
+        }),
+        "group" => RawLayer::Group(RawGroup {
+            layers: node
+                .children()
+                .filter(Node::is_element)
+                .filter_map(|layer| parse_tmx_layer(layer).transpose())
+                .collect::<Result<_>>()?,
+            name,
+        }),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(layer))
+}
+
+fn parse_tmx_object(node: Node) -> Result<RawObject> {
+    let polyline = match child(node, "polyline") {
+        Some(polyline) => Some(
+            polyline
+                .attribute("points")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(|point| {
+                    let (x, y) = point
+                        .split_once(',')
+                        .ok_or_else(|| anyhow!("invalid polyline point '{}'", point))?;
+                    Ok(RawPoint {
+                        x: x.parse()?,
+                        y: y.parse()?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        ),
+        None => None,
+    };
+
+    Ok(RawObject {
+        id: attribute(node, "id")?,
+        name: node.attribute("name").unwrap_or_default().into(),
+        ty: node.attribute("type").unwrap_or_default().into(),
+        class: node.attribute("class").unwrap_or_default().into(),
+        x: attribute(node, "x")?,
+        y: attribute(node, "y")?,
+        width: optional_attribute(node, "width")?.unwrap_or_default(),
+        height: optional_attribute(node, "height")?.unwrap_or_default(),
+        polyline,
+        properties: match child(node, "properties") {
+            Some(properties) => parse_tmx_properties(properties)?,
+            None => Vec::new(),
+        },
+    })
+}
+
+fn parse_tmx_tileset(node: Node) -> Result<RawTileset> {
+    if !node.has_tag_name("tileset") {
+        bail!("expected <tileset>, found <{}>", node.tag_name().name());
+    }
+
+    Ok(RawTileset {
+        firstgid: optional_attribute(node, "firstgid")?.unwrap_or_default(),
+        source: None,
+        name: node.attribute("name").unwrap_or_default().into(),
+        columns: optional_attribute(node, "columns")?.unwrap_or_default(),
+        tilecount: optional_attribute(node, "tilecount")?.unwrap_or_default(),
+        image: child(node, "image")
+            .and_then(|image| image.attribute("source"))
+            .map(String::from),
+        tiles: node
+            .children()
+            .filter(|tile| tile.has_tag_name("tile"))
+            .map(|tile| {
+                Ok(RawTile {
+                    id: attribute(tile, "id")?,
+                    properties: match child(tile, "properties") {
+                        Some(properties) => parse_tmx_properties(properties)?,
+                        None => Vec::new(),
+                    },
+                })
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn parse_tmx_properties(node: Node) -> Result<Vec<RawProperty>> {
+    node.children()
+        .filter(|property| property.has_tag_name("property"))
+        .map(|property| {
+            let name: String = attribute(property, "name")?;
+            let ty = property.attribute("type").unwrap_or("string");
+            // Multi-line strings are stored as the element's text
+            let text = property
+                .attribute("value")
+                .or_else(|| property.text())
+                .unwrap_or_default();
+
+            let value = match ty {
+                "bool" => Value::Bool(text == "true"),
+                "int" | "object" => Value::from(
+                    text.parse::<i64>()
+                        .with_context(|| format!("property '{}' isn't an int", name))?,
+                ),
+                "float" => Value::from(
+                    text.parse::<f64>()
+                        .with_context(|| format!("property '{}' isn't a float", name))?,
+                ),
+                _ => Value::String(text.into()),
+            };
+
+            Ok(RawProperty {
+                name,
+                ty: ty.into(),
+                value,
+            })
+        })
+        .collect()
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn attribute<T: FromStr>(node: Node, name: &str) -> Result<T> {
+    optional_attribute(node, name)?.ok_or_else(|| {
+        anyhow!(
+            "<{}> is missing the '{}' attribute",
+            node.tag_name().name(),
+            name
+        )
+    })
+}
+
+fn optional_attribute<T: FromStr>(node: Node, name: &str) -> Result<Option<T>> {
+    node.attribute(name)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                anyhow!(
+                    "invalid '{}' attribute '{}' on <{}>",
+                    name,
+                    value,
+                    node.tag_name().name()
+                )
+            })
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: &str = "tests/fixtures/tiled";
+
+    fn load(name: &str) -> Result<Level> {
+        load_level(&Path::new(FIXTURES).join(name))
+    }
+
+    fn load_error(name: &str) -> String {
+        match load(name) {
+            Ok(_) => panic!("{} loaded without an error", name),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn tmx_and_tmj_give_the_same_level() {
+        let tmx = load("room.tmx").unwrap();
+        let tmj = load("room.tmj").unwrap();
+
+        assert_eq!(
+            (tmx.map.width, tmx.map.height, tmx.map.tile_size),
+            (tmj.map.width, tmj.map.height, tmj.map.tile_size)
+        );
+        assert_eq!(tmx.map.layers, tmj.map.layers);
+        assert_eq!(tmx.tilesets, tmj.tilesets);
+        assert_eq!(tmx.objects, tmj.objects);
+        assert_eq!(tmx.properties, tmj.properties);
+    }
+
+    #[test]
+    fn tiles_take_their_flags_from_the_tileset_and_layer() {
+        let level = load("room.tmx").unwrap();
+        let names: Vec<_> = level.map.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["ground", "decor/platforms"]);
+
+        let map = &level.map;
+        assert_eq!(map.flags_at(0, 2), TileFlags::SOLID);
+        assert_eq!(map.flags_at(3, 2), TileFlags::HAZARD);
+        assert_eq!(map.flags_at(2, 1), TileFlags::SLOPE | TileFlags::SLOPE_LEFT);
+        assert_eq!(map.flags_at(0, 1), TileFlags::ONE_WAY);
+        assert_eq!(map.flags_at(0, 0), TileFlags::EMPTY);
+
+        assert_eq!(
+            level.tilesets,
+            [Tileset {
+                first_gid: 1,
+                name: "tiles".into(),
+                image: Some("tiles.png".into()),
+                columns: 4,
+                tile_count: 4,
+            }]
+        );
+        assert_eq!(
+            level.properties.get("music"),
+            Some(&PropertyValue::String("theme".into()))
+        );
+    }
+
+    #[test]
+    fn objects_keep_their_properties_and_polylines() {
+        let level = load("room.tmj").unwrap();
+        let [spawn, lift] = &level.objects[..] else {
+            panic!("expected two objects, found {:?}", level.objects);
+        };
+
+        assert_eq!(
+            (spawn.class.as_str(), spawn.position),
+            ("spawn", Vec2::new(8., 32.))
+        );
+        assert_eq!(spawn.layer, "entities");
+        assert_eq!(spawn.polyline, None);
+
+        assert_eq!(lift.class, "moving_platform");
+        assert_eq!(
+            lift.polyline.as_deref(),
+            Some(&[Vec2::new(16., 8.), Vec2::new(48., 8.), Vec2::new(48., 24.)][..])
+        );
+        assert_eq!(
+            lift.property("mode"),
+            Some(&PropertyValue::String("loop".into()))
+        );
+        assert_eq!(lift.property("one_way"), Some(&PropertyValue::Bool(true)));
+        assert_eq!(lift.property("speed"), Some(&PropertyValue::Float(24.5)));
+        assert_eq!(lift.property("wait"), Some(&PropertyValue::Int(30)));
+    }
+
+    #[test]
+    fn layer_errors_name_the_file_and_layer() {
+        for name in ["bad_layer.tmx", "bad_layer.tmj"] {
+            let error = load_error(name);
+            let expected = format!(
+                "invalid layer 'decor/broken' in {}: layer has 3 tiles, expected 12",
+                Path::new(FIXTURES).join(name).display()
+            );
+            assert_eq!(error, expected);
+        }
+    }
+
+    #[test]
+    fn flipped_tiles_are_rejected() {
+        let error = load_error("flipped.tmj");
+        assert!(
+            error.ends_with(
+                "tile 3 at column 1, row 1 is flipped or rotated, which isn't supported"
+            ),
+            "{}",
+            error
+        );
+        assert!(error.contains("invalid layer 'ground'"), "{}", error);
+    }
+}
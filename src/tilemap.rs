@@ -0,0 +1,523 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use anyhow::{bail, Result};
+use glam::{IVec2, Vec2};
+
+use crate::collision::{Aabb, Collider, Colliders};
+
+/// Gameplay properties of a tile, combined as a bit set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TileFlags(u8);
+
+impl TileFlags {
+    pub const EMPTY: Self = Self(0);
+    pub const SOLID: Self = Self(1);
+    pub const ONE_WAY: Self = Self(1 << 1);
+    pub const HAZARD: Self = Self(1 << 2);
+    /// A 45° slope.
+    pub const SLOPE: Self = Self(1 << 3);
+    /// The lower half of a 22.5° slope, rising from nothing to half the tile.
+    pub const HALF_SLOPE_LOW: Self = Self(1 << 4);
+    /// The upper half of a 22.5° slope, rising from half the tile to all of
+    /// it.
+    pub const HALF_SLOPE_HIGH: Self = Self(1 << 5);
+    /// Makes a slope rise towards the left instead of the right.
+    pub const SLOPE_LEFT: Self = Self(1 << 6);
+    /// Makes a slope hang from the top of the tile instead of standing on the
+    /// bottom.
+    pub const SLOPE_CEILING: Self = Self(1 << 7);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// How far a slope tile's surface is from its base at the tile's left and
+    /// right edges, as fractions of the tile. The base is the bottom of the
+    /// tile, or the top for ceiling slopes.
+    pub fn slope_heights(self) -> Option<(f32, f32)> {
+        let (low, high) = if self.contains(Self::SLOPE) {
+            (0., 1.)
+        } else if self.contains(Self::HALF_SLOPE_LOW) {
+            (0., 0.5)
+        } else if self.contains(Self::HALF_SLOPE_HIGH) {
+            (0.5, 1.)
+        } else {
+            return None;
+        };
+
+        Some(if self.contains(Self::SLOPE_LEFT) {
+            (high, low)
+        } else {
+            (low, high)
+        })
+    }
+}
+
+impl BitOr for TileFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for TileFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// A single cell of a layer. An `id` of 0 means there is no tile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tile {
+    pub id: u32,
+    pub flags: TileFlags,
+}
+
+impl Tile {
+    pub const EMPTY: Self = Self {
+        id: 0,
+        flags: TileFlags::EMPTY,
+    };
+}
+
+/// A full grid of tiles, stored row by row.
+#[derive(Debug, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub tiles: Vec<Tile>,
+}
+
+/// A level grid made of one or more layers of equally sized square tiles. The
+/// top-left corner of tile (0, 0) sits at the world origin.
+pub struct TileMap {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: f32,
+    pub layers: Vec<TileLayer>,
+    /// The flags of every layer merged per cell, so collision queries only
+    /// have to look at one grid.
+    collision: Vec<TileFlags>,
+}
+
+impl TileMap {
+    pub fn new(width: u32, height: u32, tile_size: f32) -> Self {
+        Self {
+            width,
+            height,
+            tile_size,
+            layers: Vec::new(),
+            collision: vec![TileFlags::EMPTY; (width * height) as usize],
+        }
+    }
+
+    /// Builds a single layer map from a text layout, one line per row:
+    ///
+    /// - `.` or a space is empty
+    /// - `#` is solid
+    /// - `=` is a one-way platform
+    /// - `^` is a hazard
+    /// - `/` and `\` are 45° slopes rising to the right and to the left
+    /// - `r` then `R` is a 22.5° slope rising to the right over two tiles,
+    ///   and `L` then `l` one rising to the left
+    /// - `7` and `F` are 45° ceiling slopes, solid in the top-right and
+    ///   top-left halves of the tile
+    /// - `q` then `Q` is a 22.5° ceiling slope getting lower to the right
+    ///   over two tiles, and `P` then `p` one getting lower to the left
+    ///
+    /// Leading and trailing blank lines are ignored, which makes it easy to
+    /// write layouts as raw string literals.
+    pub fn from_ascii(layout: &str, tile_size: f32) -> Result<Self> {
+        let rows: Vec<&str> = layout
+            .lines()
+            .skip_while(|line| line.trim().is_empty())
+            .collect();
+        let rows = match rows.iter().rposition(|line| !line.trim().is_empty()) {
+            Some(last) => &rows[..=last],
+            None => bail!("tile map layout is empty"),
+        };
+
+        let width = rows[0].chars().count();
+        let mut tiles = Vec::with_capacity(width * rows.len());
+
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                bail!(
+                    "row {} of the tile map layout is {} tiles wide, expected {}",
+                    y,
+                    row.chars().count(),
+                    width
+                );
+            }
+
+            for (x, c) in row.chars().enumerate() {
+                let tile = match c {
+                    '.' | ' ' => Tile::EMPTY,
+                    '#' => Tile {
+                        id: 1,
+                        flags: TileFlags::SOLID,
+                    },
+                    '=' => Tile {
+                        id: 2,
+                        flags: TileFlags::ONE_WAY,
+                    },
+                    '^' => Tile {
+                        id: 3,
+                        flags: TileFlags::HAZARD,
+                    },
+                    '/' => Tile {
+                        id: 4,
+                        flags: TileFlags::SLOPE,
+                    },
+                    '\\' => Tile {
+                        id: 5,
+                        flags: TileFlags::SLOPE | TileFlags::SLOPE_LEFT,
+                    },
+                    'r' => Tile {
+                        id: 6,
+                        flags: TileFlags::HALF_SLOPE_LOW,
+                    },
+                    'R' => Tile {
+                        id: 7,
+                        flags: TileFlags::HALF_SLOPE_HIGH,
+                    },
+                    'l' => Tile {
+                        id: 8,
+                        flags: TileFlags::HALF_SLOPE_LOW | TileFlags::SLOPE_LEFT,
+                    },
+                    'L' => Tile {
+                        id: 9,
+                        flags: TileFlags::HALF_SLOPE_HIGH | TileFlags::SLOPE_LEFT,
+                    },
+                    '7' => Tile {
+                        id: 10,
+                        flags: TileFlags::SLOPE | TileFlags::SLOPE_CEILING,
+                    },
+                    'F' => Tile {
+                        id: 11,
+                        flags: TileFlags::SLOPE | TileFlags::SLOPE_LEFT | TileFlags::SLOPE_CEILING,
+                    },
+                    'q' => Tile {
+                        id: 12,
+                        flags: TileFlags::HALF_SLOPE_LOW | TileFlags::SLOPE_CEILING,
+                    },
+                    'Q' => Tile {
+                        id: 13,
+                        flags: TileFlags::HALF_SLOPE_HIGH | TileFlags::SLOPE_CEILING,
+                    },
+                    'p' => Tile {
+                        id: 14,
+                        flags: TileFlags::HALF_SLOPE_LOW
+                            | TileFlags::SLOPE_LEFT
+                            | TileFlags::SLOPE_CEILING,
+                    },
+                    'P' => Tile {
+                        id: 15,
+                        flags: TileFlags::HALF_SLOPE_HIGH
+                            | TileFlags::SLOPE_LEFT
+                            | TileFlags::SLOPE_CEILING,
+                    },
+                    _ => bail!("unknown tile '{}' at column {}, row {}", c, x, y),
+                };
+
+                tiles.push(tile);
+            }
+        }
+
+        let mut map = Self::new(width as u32, rows.len() as u32, tile_size);
+        map.add_layer(TileLayer {
+            name: "ascii".into(),
+            tiles,
+        });
+
+        Ok(map)
+    }
+
+    pub fn add_layer(&mut self, layer: TileLayer) {
+        assert_eq!(
+            layer.tiles.len(),
+            self.collision.len(),
+            "layer '{}' doesn't match the size of the map",
+            layer.name
+        );
+
+        for (flags, tile) in self.collision.iter_mut().zip(&layer.tiles) {
+            *flags |= tile.flags;
+        }
+
+        self.layers.push(layer);
+    }
+
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, tile: Tile) {
+        let index = self.index(x, y);
+        self.layers[layer].tiles[index] = tile;
+
+        self.collision[index] = self.layers.iter().fold(TileFlags::EMPTY, |flags, layer| {
+            flags | layer.tiles[index].flags
+        });
+    }
+
+    /// The merged flags of the cell at (`x`, `y`). Cells outside of the map
+    /// are empty.
+    pub fn flags_at(&self, x: i32, y: i32) -> TileFlags {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return TileFlags::EMPTY;
+        }
+
+        self.collision[self.index(x as u32, y as u32)]
+    }
+
+    /// The cell containing the world position `position`.
+    pub fn world_to_tile(&self, position: Vec2) -> IVec2 {
+        (position / self.tile_size).floor().as_ivec2()
+    }
+
+    pub fn tile_bounds(&self, x: i32, y: i32) -> Aabb {
+        Aabb::from_position_size(
+            Vec2::new(x as f32, y as f32) * self.tile_size,
+            Vec2::splat(self.tile_size),
+        )
+    }
+
+    /// The world space bounds of the whole map.
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(
+            Vec2::ZERO,
+            Vec2::new(self.width as f32, self.height as f32) * self.tile_size,
+        )
+    }
+
+    /// Every cell overlapping `region`, clamped to the map.
+    pub fn cells_in(&self, region: &Aabb) -> impl Iterator<Item = IVec2> {
+        let min = self.world_to_tile(region.min).max(IVec2::ZERO);
+        let max = (region.max / self.tile_size)
+            .ceil()
+            .as_ivec2()
+            .min(IVec2::new(self.width as i32, self.height as i32));
+
+        (min.y..max.y).flat_map(move |y| (min.x..max.x).map(move |x| IVec2::new(x, y)))
+    }
+
+    /// Whether `region` overlaps any cell with one of `flags` set, e.g. to
+    /// check if the player touched a hazard.
+    pub fn overlaps(&self, region: &Aabb, flags: TileFlags) -> bool {
+        self.cells_in(region).any(|cell| {
+            self.flags_at(cell.x, cell.y).intersects(flags)
+                && self.tile_bounds(cell.x, cell.y).intersects(region)
+        })
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+}
+
+impl Colliders for TileMap {
+    fn colliders_in(&self, region: &Aabb, out: &mut Vec<Collider>) {
+        for cell in self.cells_in(region) {
+            let flags = self.flags_at(cell.x, cell.y);
+            let bounds = self.tile_bounds(cell.x, cell.y);
+            if flags.contains(TileFlags::SOLID) {
+                out.push(Collider::solid(bounds));
+            } else if let Some((left, right)) = flags.slope_heights() {
+                out.push(Collider::slope(
+                    bounds,
+                    left * self.tile_size,
+                    right * self.tile_size,
+                    flags.contains(TileFlags::SLOPE_CEILING),
+                ));
+            } else if flags.contains(TileFlags::ONE_WAY) {
+                out.push(Collider::one_way(bounds));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r"
+.....
+.=^..
+.###.
+";
+
+    fn map() -> TileMap {
+        TileMap::from_ascii(LAYOUT, 16.).unwrap()
+    }
+
+    fn region(min: Vec2, max: Vec2) -> Aabb {
+        Aabb::new(min, max)
+    }
+
+    #[test]
+    fn from_ascii_reads_the_flags_of_each_cell() {
+        let map = map();
+
+        assert_eq!((map.width, map.height), (5, 3));
+        assert_eq!(map.flags_at(1, 1), TileFlags::ONE_WAY);
+        assert_eq!(map.flags_at(2, 1), TileFlags::HAZARD);
+        assert_eq!(map.flags_at(3, 2), TileFlags::SOLID);
+        assert!(map.flags_at(0, 0).is_empty());
+        // Outside of the map
+        assert!(map.flags_at(-1, 2).is_empty());
+        assert!(map.flags_at(1, 3).is_empty());
+    }
+
+    #[test]
+    fn from_ascii_rejects_ragged_and_unknown_layouts() {
+        let Err(ragged) = TileMap::from_ascii("...\n..\n", 16.) else {
+            panic!("rows of different widths");
+        };
+        assert_eq!(
+            ragged.to_string(),
+            "row 1 of the tile map layout is 2 tiles wide, expected 3"
+        );
+
+        let Err(unknown) = TileMap::from_ascii("..x", 16.) else {
+            panic!("'x' isn't a tile");
+        };
+        assert_eq!(unknown.to_string(), "unknown tile 'x' at column 2, row 0");
+
+        assert!(TileMap::from_ascii("\n  \n", 16.).is_err());
+    }
+
+    #[test]
+    fn solid_and_one_way_tiles_are_colliders() {
+        let map = map();
+        let mut colliders = Vec::new();
+        map.colliders_in(&map.bounds(), &mut colliders);
+
+        assert_eq!(
+            colliders,
+            [
+                Collider::one_way(map.tile_bounds(1, 1)),
+                Collider::solid(map.tile_bounds(1, 2)),
+                Collider::solid(map.tile_bounds(2, 2)),
+                Collider::solid(map.tile_bounds(3, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn colliders_are_only_looked_up_near_the_region() {
+        let map = map();
+        let mut colliders = Vec::new();
+        map.colliders_in(
+            &region(Vec2::new(50., 36.), Vec2::new(60., 40.)),
+            &mut colliders,
+        );
+
+        assert_eq!(colliders, [Collider::solid(map.tile_bounds(3, 2))]);
+    }
+
+    #[test]
+    fn cells_in_is_clamped_to_the_map() {
+        let map = map();
+
+        let everything: Vec<IVec2> = map
+            .cells_in(&region(Vec2::splat(-100.), Vec2::splat(1000.)))
+            .collect();
+        assert_eq!(everything.len(), 15);
+        assert_eq!(everything[0], IVec2::ZERO);
+        assert_eq!(everything[14], IVec2::new(4, 2));
+
+        let corner: Vec<IVec2> = map
+            .cells_in(&region(Vec2::new(-20., -20.), Vec2::new(8., 8.)))
+            .collect();
+        assert_eq!(corner, [IVec2::ZERO]);
+
+        let outside = region(Vec2::new(100., 0.), Vec2::new(120., 20.));
+        assert_eq!(map.cells_in(&outside).count(), 0);
+    }
+
+    #[test]
+    fn cells_in_doesnt_include_cells_the_region_only_touches() {
+        let map = map();
+        let cells: Vec<IVec2> = map
+            .cells_in(&region(Vec2::new(16., 16.), Vec2::new(32., 32.)))
+            .collect();
+
+        assert_eq!(cells, [IVec2::new(1, 1)]);
+    }
+
+    #[test]
+    fn overlaps_finds_hazards() {
+        let map = map();
+
+        assert!(map.overlaps(
+            &region(Vec2::new(36., 26.), Vec2::new(44., 34.)),
+            TileFlags::HAZARD
+        ));
+        // Touching the hazard's edge doesn't count
+        assert!(!map.overlaps(
+            &region(Vec2::new(48., 20.), Vec2::new(56., 28.)),
+            TileFlags::HAZARD
+        ));
+        // Nor does overlapping some other kind of tile
+        assert!(!map.overlaps(
+            &region(Vec2::new(20., 20.), Vec2::new(28., 28.)),
+            TileFlags::HAZARD
+        ));
+        assert!(map.overlaps(
+            &region(Vec2::new(20., 20.), Vec2::new(28., 28.)),
+            TileFlags::HAZARD | TileFlags::ONE_WAY
+        ));
+    }
+
+    /// The collider for each tile in the only row of `layout`.
+    fn row_colliders(layout: &str) -> Vec<Collider> {
+        let map = TileMap::from_ascii(layout, 16.).unwrap();
+        let mut out = Vec::new();
+        map.colliders_in(&map.bounds(), &mut out);
+        out
+    }
+
+    fn slope(x: f32, left: f32, right: f32, ceiling: bool) -> Collider {
+        Collider::slope(
+            Aabb::new(Vec2::new(x, 0.), Vec2::new(x + 16., 16.)),
+            left,
+            right,
+            ceiling,
+        )
+    }
+
+    #[test]
+    fn floor_slopes() {
+        assert_eq!(
+            row_colliders("/\\rRLl"),
+            [
+                slope(0., 0., 16., false),
+                slope(16., 16., 0., false),
+                slope(32., 0., 8., false),
+                slope(48., 8., 16., false),
+                slope(64., 16., 8., false),
+                slope(80., 8., 0., false),
+            ]
+        );
+    }
+
+    #[test]
+    fn ceiling_slopes() {
+        assert_eq!(
+            row_colliders("7FqQPp"),
+            [
+                slope(0., 0., 16., true),
+                slope(16., 16., 0., true),
+                slope(32., 0., 8., true),
+                slope(48., 8., 16., true),
+                slope(64., 16., 8., true),
+                slope(80., 8., 0., true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+/// Accumulates frame time and hands it out as a whole number of fixed-length
+/// simulation ticks, so gameplay runs at the same speed regardless of the
+/// display refresh rate.
+pub struct FixedTimestep {
+    pub tick_rate: u32,
+    pub max_steps: u32,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32, max_steps: u32) -> Self {
+        Self {
+            tick_rate,
+            max_steps,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// The length of one tick in seconds.
+    pub fn dt(&self) -> f32 {
+        1. / self.tick_rate as f32
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1. / self.tick_rate as f64)
+    }
+
+    /// Adds `elapsed` to the accumulator and returns how many ticks should be
+    /// simulated. At most `max_steps` ticks are returned; any time beyond that
+    /// is dropped so a long stall doesn't send the simulation into a spiral of
+    /// ever longer catch-up frames.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        let tick_duration = self.tick_duration();
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= tick_duration && steps < self.max_steps {
+            self.accumulator -= tick_duration;
+            steps += 1;
+        }
+
+        if steps == self.max_steps && self.accumulator >= tick_duration {
+            self.accumulator = Duration::ZERO;
+        }
+
+        steps
+    }
+
+    /// How far we are between the last simulated tick and the next one, in
+    /// the range `0..1`. Used by rendering to interpolate between the previous
+    /// and current simulation state.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.tick_duration().as_secs_f64()) as f32
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(60, 5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_counts_whole_ticks() {
+        let mut timestep = FixedTimestep::default();
+        let tick = timestep.tick_duration();
+
+        assert_eq!(timestep.advance(tick), 1);
+        assert_eq!(timestep.advance(tick * 3), 3);
+        assert_eq!(timestep.advance(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn partial_ticks_carry_over() {
+        // 20ms ticks, which halve exactly
+        let mut timestep = FixedTimestep::new(50, 5);
+        let tick = timestep.tick_duration();
+
+        assert_eq!(timestep.advance(tick / 2), 0);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(timestep.advance(tick / 2), 1);
+        assert!(timestep.alpha() < 1e-6);
+
+        // Frames faster than the tick rate tick every other frame
+        let steps: Vec<u32> = (0..6).map(|_| timestep.advance(tick / 2)).collect();
+        assert_eq!(steps, [0, 1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut timestep = FixedTimestep::default();
+
+        // Frame times that don't line up with the tick length
+        for millis in [7, 16, 17, 33, 1, 25, 16, 50, 3, 70] {
+            timestep.advance(Duration::from_millis(millis));
+            let alpha = timestep.alpha();
+            assert!(
+                (0. ..1.).contains(&alpha),
+                "alpha {} after {}ms",
+                alpha,
+                millis
+            );
+        }
+    }
+
+    #[test]
+    fn long_stalls_are_clamped_to_max_steps() {
+        let mut timestep = FixedTimestep::new(60, 5);
+        let tick = timestep.tick_duration();
+
+        assert_eq!(timestep.advance(Duration::from_secs(2)), 5);
+        // The rest of the stall is dropped rather than caught up on later
+        assert_eq!(timestep.alpha(), 0.);
+        assert_eq!(timestep.advance(tick), 1);
+    }
+
+    #[test]
+    fn time_just_under_max_steps_isnt_dropped() {
+        let mut timestep = FixedTimestep::new(50, 5);
+        let tick = timestep.tick_duration();
+
+        assert_eq!(timestep.advance(tick * 5 + tick / 2), 5);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(timestep.advance(tick / 2), 1);
+    }
+}
@@ -0,0 +1,185 @@
+use std::f32::consts::PI;
+
+/// How a tween speeds up and slows down between its start and end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicInOut,
+    SineInOut,
+}
+
+impl Easing {
+    /// Maps linear progress `t` in `0..=1` to eased progress.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1. - (1. - t) * (1. - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2. * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(2) / 2.
+                }
+            }
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(3) / 2.
+                }
+            }
+            Easing::SineInOut => -((PI * t).cos() - 1.) / 2.,
+        }
+    }
+
+    /// Parses names like `"quad_in_out"`, as used in level files.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "quad_in" => Some(Easing::QuadIn),
+            "quad_out" => Some(Easing::QuadOut),
+            "quad_in_out" => Some(Easing::QuadInOut),
+            "cubic_in_out" => Some(Easing::CubicInOut),
+            "sine_in_out" => Some(Easing::SineInOut),
+            _ => None,
+        }
+    }
+}
+
+/// Moves a value from `from` to `to` over `duration` seconds.
+#[derive(Clone, Copy, Debug)]
+pub struct Tween {
+    pub from: f32,
+    pub to: f32,
+    pub duration: f32,
+    pub easing: Easing,
+    elapsed: f32,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, duration: f32, easing: Easing) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            easing,
+            elapsed: 0.,
+        }
+    }
+
+    /// Advances the tween by `dt` seconds and returns its new value.
+    pub fn update(&mut self, dt: f32) -> f32 {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+        self.value()
+    }
+
+    /// How far along the tween is, after easing, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0. {
+            return 1.;
+        }
+
+        self.easing.apply(self.elapsed / self.duration)
+    }
+
+    pub fn value(&self) -> f32 {
+        self.from + (self.to - self.from) * self.progress()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASINGS: [Easing; 6] = [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicInOut,
+        Easing::SineInOut,
+    ];
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in EASINGS {
+            assert_eq!(easing.apply(0.), 0., "{:?}", easing);
+            assert!((easing.apply(1.) - 1.).abs() < 1e-6, "{:?}", easing);
+            // Progress outside the tween is clamped
+            assert_eq!(easing.apply(-1.), easing.apply(0.), "{:?}", easing);
+            assert_eq!(easing.apply(2.), easing.apply(1.), "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easings_never_go_backwards() {
+        for easing in EASINGS {
+            let mut previous = 0.;
+            for i in 1..=100 {
+                let progress = easing.apply(i as f32 / 100.);
+                assert!(progress >= previous, "{:?} at {}", easing, i);
+                previous = progress;
+            }
+        }
+    }
+
+    #[test]
+    fn in_out_easings_are_halfway_at_the_middle() {
+        for easing in [
+            Easing::Linear,
+            Easing::QuadInOut,
+            Easing::CubicInOut,
+            Easing::SineInOut,
+        ] {
+            assert!((easing.apply(0.5) - 0.5).abs() < 1e-6, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easing_names_parse() {
+        for (name, easing) in [
+            "linear",
+            "quad_in",
+            "quad_out",
+            "quad_in_out",
+            "cubic_in_out",
+            "sine_in_out",
+        ]
+        .into_iter()
+        .zip(EASINGS)
+        {
+            assert_eq!(Easing::from_name(name), Some(easing));
+        }
+        assert_eq!(Easing::from_name("bounce"), None);
+    }
+
+    #[test]
+    fn tween_stops_at_its_end() {
+        let mut tween = Tween::new(10., 20., 0.5, Easing::Linear);
+        assert_eq!(tween.value(), 10.);
+        assert_eq!(tween.update(0.25), 15.);
+        assert!(!tween.is_finished());
+
+        assert_eq!(tween.update(1.), 20.);
+        assert!(tween.is_finished());
+        assert_eq!(tween.update(1.), 20.);
+    }
+
+    #[test]
+    fn zero_length_tweens_are_already_finished() {
+        let tween = Tween::new(10., 20., 0., Easing::QuadIn);
+        assert!(tween.is_finished());
+        assert_eq!(tween.value(), 20.);
+    }
+}
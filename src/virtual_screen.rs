@@ -0,0 +1,236 @@
+use glam::{UVec2, Vec2};
+use wgpu::{
+    include_wgsl, BindGroupLayout, Color, CommandEncoder, Device, LoadOp, Operations,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, TextureFormat, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::{model::Material, texture::Texture};
+
+/// Where the virtual screen ends up in the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// The top-left corner, in physical pixels.
+    pub position: Vec2,
+    /// The size, in physical pixels.
+    pub size: Vec2,
+    /// How many physical pixels wide each virtual pixel is.
+    pub scale: f32,
+}
+
+impl Viewport {
+    /// Scales `resolution` up to fill as much of `window_size` as it can,
+    /// centering it between letterbox or pillarbox bars.
+    ///
+    /// Only whole scale factors are used so every virtual pixel covers the
+    /// same number of physical ones. Windows smaller than the virtual
+    /// resolution shrink it by whatever fits instead.
+    pub fn fit(resolution: UVec2, window_size: PhysicalSize<u32>) -> Self {
+        let window = Vec2::new(window_size.width as f32, window_size.height as f32);
+        let fit = (window / resolution.as_vec2()).min_element();
+        let scale = if fit >= 1. { fit.floor() } else { fit };
+
+        let size = resolution.as_vec2() * scale;
+        Self {
+            position: ((window - size) / 2.).floor(),
+            size,
+            scale,
+        }
+    }
+
+    /// Converts a point in the window, e.g. the mouse cursor, to a point on
+    /// the virtual screen.
+    pub fn to_virtual(&self, point: Vec2) -> Vec2 {
+        (point - self.position) / self.scale
+    }
+}
+
+/// An offscreen target the game is drawn to at a fixed resolution, so every
+/// window shows the same playfield, before being scaled up to the window.
+pub struct VirtualScreen {
+    pub resolution: UVec2,
+    pub viewport: Viewport,
+    /// The color of the bars around the scaled up screen.
+    pub border_color: Color,
+    target: Material,
+    pipeline: RenderPipeline,
+}
+
+impl VirtualScreen {
+    pub fn new(
+        device: &Device,
+        resolution: UVec2,
+        format: TextureFormat,
+        window_size: PhysicalSize<u32>,
+        texture_bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let texture = Texture::create_render_target(
+            device,
+            resolution.x,
+            resolution.y,
+            format,
+            "Virtual Screen",
+        );
+        let target = Material::new(device, "Virtual Screen", texture, texture_bind_group_layout);
+
+        let shader = device.create_shader_module(include_wgsl!("blit.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            resolution,
+            viewport: Viewport::fit(resolution, window_size),
+            border_color: Color::BLACK,
+            target,
+            pipeline,
+        }
+    }
+
+    /// The texture to draw the game into.
+    pub fn target(&self) -> &Texture {
+        &self.target.diffuse_texture
+    }
+
+    pub fn resize(&mut self, window_size: PhysicalSize<u32>) {
+        self.viewport = Viewport::fit(self.resolution, window_size);
+    }
+
+    /// Scales the virtual screen up onto `view`, filling the rest of it with
+    /// the border color.
+    pub fn draw(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Blit Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(self.border_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        let Viewport { position, size, .. } = self.viewport;
+        render_pass.set_viewport(position.x, position.y, size.x, size.y, 0., 1.);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::LogicalSize;
+
+    use super::*;
+
+    const RESOLUTION: UVec2 = UVec2::new(320, 180);
+
+    fn fit(width: u32, height: u32) -> Viewport {
+        Viewport::fit(RESOLUTION, PhysicalSize::new(width, height))
+    }
+
+    #[test]
+    fn exact_fit_fills_the_window() {
+        let viewport = fit(1920, 1080);
+        assert_eq!(viewport.scale, 6.);
+        assert_eq!(viewport.position, Vec2::ZERO);
+        assert_eq!(viewport.size, Vec2::new(1920., 1080.));
+    }
+
+    #[test]
+    fn non_integer_fit_rounds_down() {
+        // 2.5x fits, but only 2x is used
+        let viewport = fit(800, 450);
+        assert_eq!(viewport.scale, 2.);
+        assert_eq!(viewport.size, Vec2::new(640., 360.));
+        assert_eq!(viewport.position, Vec2::new(80., 45.));
+    }
+
+    #[test]
+    fn tall_window_is_letterboxed() {
+        let viewport = fit(1280, 1024);
+        assert_eq!(viewport.scale, 4.);
+        assert_eq!(viewport.size, Vec2::new(1280., 720.));
+        assert_eq!(viewport.position, Vec2::new(0., 152.));
+    }
+
+    #[test]
+    fn wide_window_is_pillarboxed() {
+        let viewport = fit(2560, 1080);
+        assert_eq!(viewport.scale, 6.);
+        assert_eq!(viewport.size, Vec2::new(1920., 1080.));
+        assert_eq!(viewport.position, Vec2::new(320., 0.));
+    }
+
+    #[test]
+    fn odd_leftover_space_stays_on_whole_pixels() {
+        let viewport = fit(645, 365);
+        assert_eq!(viewport.scale, 2.);
+        assert_eq!(viewport.position, Vec2::new(2., 2.));
+    }
+
+    #[test]
+    fn window_smaller_than_the_resolution_shrinks_it() {
+        let viewport = fit(160, 120);
+        assert_eq!(viewport.scale, 0.5);
+        assert_eq!(viewport.size, Vec2::new(160., 90.));
+        assert_eq!(viewport.position, Vec2::new(0., 15.));
+    }
+
+    #[test]
+    fn hidpi_windows_fit_their_physical_size() {
+        for (scale_factor, scale, position) in [
+            (1., 2., Vec2::new(80., 120.)),
+            (1.5, 3., Vec2::new(120., 180.)),
+            (2., 5., Vec2::new(0., 150.)),
+        ] {
+            let window_size = LogicalSize::new(800, 600).to_physical(scale_factor);
+            let viewport = Viewport::fit(RESOLUTION, window_size);
+            assert_eq!(viewport.scale, scale, "at {}x", scale_factor);
+            assert_eq!(viewport.position, position, "at {}x", scale_factor);
+        }
+    }
+
+    #[test]
+    fn to_virtual_maps_the_viewport_corners() {
+        let viewport = fit(1280, 1024);
+        assert_eq!(viewport.to_virtual(viewport.position), Vec2::ZERO);
+        assert_eq!(
+            viewport.to_virtual(viewport.position + viewport.size),
+            RESOLUTION.as_vec2()
+        );
+        assert_eq!(
+            viewport.to_virtual(Vec2::new(2., 154.)),
+            Vec2::new(0.5, 0.5)
+        );
+    }
+}
@@ -0,0 +1,708 @@
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec4};
+
+use crate::{
+    animation::{AnimationClip, AnimationFrame, AnimationState, Animator, PlaybackMode},
+    body::{Body, JumpProfile, MovementProfile},
+    collision::Aabb,
+    input::{Action, InputFrame, InputState},
+    instance::Instance,
+    level::Level,
+    model::ModelController,
+    platform::{update_platforms, MovingPlatform},
+    tilemap::TileFlags,
+};
+
+/// The player's animations. Frames are named after their image, so the
+/// atlas can be built straight from these.
+pub fn player_animations() -> HashMap<AnimationState, AnimationClip> {
+    let frame = |name: &str, duration| {
+        AnimationFrame::new(&format!("textures/player/{}.png", name), duration)
+    };
+
+    HashMap::from([
+        (
+            AnimationState::Idle,
+            AnimationClip::new(
+                vec![frame("idle_0", 0.5), frame("idle_1", 0.5)],
+                PlaybackMode::Loop,
+            ),
+        ),
+        (
+            AnimationState::Run,
+            AnimationClip::new(
+                vec![
+                    frame("run_0", 0.1).with_event("footstep"),
+                    frame("run_1", 0.1),
+                    frame("run_2", 0.1).with_event("footstep"),
+                    frame("run_3", 0.1),
+                ],
+                PlaybackMode::Loop,
+            ),
+        ),
+        (
+            AnimationState::Jump,
+            AnimationClip::new(vec![frame("jump_0", 0.1)], PlaybackMode::Once),
+        ),
+        (
+            AnimationState::Fall,
+            AnimationClip::new(vec![frame("fall_0", 0.1)], PlaybackMode::Once),
+        ),
+    ])
+}
+
+/// How fast the player has to be falling for landing to count as hard, in
+/// pixels per second.
+const HARD_LANDING_SPEED: f32 = 400.;
+
+/// Something that happened during a tick, for whatever presents the world to
+/// react to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorldEvent {
+    /// The player hit the ground falling fast.
+    HardLanding,
+    /// The player got crushed by a moving platform and was sent back to the
+    /// spawn point.
+    Crushed,
+    /// The player touched a hazard tile and was sent back to the spawn point.
+    Hurt,
+    /// A frame with an event started showing in the player's animation.
+    Animation(String),
+}
+
+/// A small, fast random number generator (SplitMix64). Everything random in
+/// the simulation has to come from here so it plays out the same for the
+/// same seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to, but not including, 1.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Picks one of `items` at random.
+    pub fn choose<T: Copy>(&mut self, items: &[T]) -> Option<T> {
+        if items.is_empty() {
+            return None;
+        }
+
+        Some(items[(self.next_u64() % items.len() as u64) as usize])
+    }
+}
+
+/// The simulation: everything that changes tick by tick, and nothing needed
+/// only to draw it. Given the same level, seed and input it always plays out
+/// the same way.
+pub struct World {
+    pub level: Level,
+    pub platforms: Vec<MovingPlatform>,
+    pub model_controller: ModelController,
+    /// The player.
+    pub instance: Instance,
+    /// The player as it was before the last tick, to draw in between ticks.
+    pub previous_instance: Instance,
+    pub body: Body,
+    pub animator: Animator,
+    pub input: InputState,
+    pub paused: bool,
+    /// What `rng` started from.
+    pub seed: u64,
+    pub rng: Rng,
+    /// How many ticks have run.
+    pub ticks: u64,
+    /// What happened during the last tick.
+    pub events: Vec<WorldEvent>,
+}
+
+impl World {
+    pub fn new(level: Level, seed: u64) -> Self {
+        let model_controller = ModelController::new(MovementProfile {
+            speed: 120.,
+            jump: JumpProfile {
+                jump_height: 56.,
+                time_to_apex: 0.35,
+            },
+            ..Default::default()
+        });
+
+        let mut rng = Rng::new(seed);
+        let player_size = 16.;
+        let instance = Instance {
+            position: spawn_point(&level, &mut rng) - Vec2::new(0., player_size),
+            rotation: 0.,
+            scale: player_size,
+            tint: Vec4::ONE,
+            uv_rect: Instance::FULL_UV_RECT,
+        };
+
+        Self {
+            platforms: MovingPlatform::from_level(&level),
+            level,
+            model_controller,
+            instance,
+            previous_instance: instance,
+            body: Body::new(
+                Aabb::from_position_size(Vec2::ZERO, Vec2::splat(player_size)),
+                500.,
+            ),
+            animator: Animator::new(player_animations(), AnimationState::Idle),
+            input: InputState::default(),
+            paused: false,
+            seed,
+            rng,
+            ticks: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Runs a single simulation step of `dt` seconds with the input in
+    /// `frame`.
+    pub fn step(&mut self, frame: InputFrame, dt: f32) {
+        self.events.clear();
+        self.ticks += 1;
+
+        self.input.update(frame, dt);
+        if self.input.just_pressed(Action::Pause) {
+            self.paused = !self.paused;
+        }
+
+        self.previous_instance = self.instance;
+        if self.paused {
+            return;
+        }
+
+        // Platforms move first, so the player moves relative to where
+        // they're standing this tick
+        if update_platforms(
+            &mut self.platforms,
+            &mut self.instance,
+            &mut self.body,
+            &self.level,
+            dt,
+        ) {
+            self.respawn();
+            self.events.push(WorldEvent::Crushed);
+            return;
+        }
+
+        let fall_speed = self.body.velocity.y;
+
+        self.model_controller.set_input(&self.input);
+        self.model_controller.update_instance(
+            &mut self.instance,
+            &mut self.body,
+            &(&self.level, self.platforms.as_slice()),
+            dt,
+        );
+
+        let bounds = self.body.bounds(self.instance.position);
+        if self.level.map.overlaps(&bounds, TileFlags::HAZARD) {
+            self.respawn();
+            self.events.push(WorldEvent::Hurt);
+            return;
+        }
+
+        if self.body.grounded && fall_speed > HARD_LANDING_SPEED {
+            self.events.push(WorldEvent::HardLanding);
+        }
+
+        let mut animation_events = Vec::new();
+        self.animator.update(&self.body, dt, &mut animation_events);
+        self.events
+            .extend(animation_events.into_iter().map(WorldEvent::Animation));
+    }
+
+    /// Puts the player back at a spawn point, standing still.
+    pub fn respawn(&mut self) {
+        self.instance.position =
+            spawn_point(&self.level, &mut self.rng) - Vec2::new(0., self.instance.scale);
+        self.previous_instance = self.instance;
+        self.body.velocity = Vec2::ZERO;
+        self.body.grounded = false;
+        self.model_controller = ModelController::new(self.model_controller.profile);
+    }
+
+    /// A fingerprint of the world's state, for checking that a replay ended
+    /// up exactly where the recording did.
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a over the exact bits, so even the smallest drift shows up
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+
+        write(&self.ticks.to_le_bytes());
+        for value in [
+            self.instance.position.x,
+            self.instance.position.y,
+            self.body.velocity.x,
+            self.body.velocity.y,
+        ] {
+            write(&value.to_bits().to_le_bytes());
+        }
+        for platform in &self.platforms {
+            write(&platform.position.x.to_bits().to_le_bytes());
+            write(&platform.position.y.to_bits().to_le_bytes());
+        }
+        write(&[self.body.grounded as u8, self.paused as u8]);
+        write(&self.rng.state.to_le_bytes());
+
+        hash
+    }
+}
+
+/// Where the player's feet go when they spawn, picked at random when the
+/// level has more than one spawn point.
+fn spawn_point(level: &Level, rng: &mut Rng) -> Vec2 {
+    let spawn_points: Vec<_> = level.spawn_points().collect();
+    rng.choose(&spawn_points).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        input::{ActionSet, Axis},
+        level::{LevelObject, PropertyValue},
+        resources,
+        state::LEVEL,
+        tilemap::TileMap,
+        timestep::FixedTimestep,
+    };
+
+    const DT: f32 = 1. / 60.;
+
+    /// A level of 16x16 tiles laid out as in `TileMap::from_ascii`, with the
+    /// player's feet spawning at one of `spawns`.
+    fn level(layout: &str, spawns: &[Vec2]) -> Level {
+        Level {
+            map: TileMap::from_ascii(layout, 16.).unwrap(),
+            tilesets: Vec::new(),
+            objects: spawns
+                .iter()
+                .zip(1..)
+                .map(|(&position, id)| LevelObject {
+                    id,
+                    name: "spawn".into(),
+                    class: "spawn".into(),
+                    layer: "objects".into(),
+                    position,
+                    size: Vec2::ZERO,
+                    polyline: None,
+                    properties: Default::default(),
+                })
+                .collect(),
+            properties: Default::default(),
+        }
+    }
+
+    fn holding(actions: &[Action]) -> InputFrame {
+        let mut frame = InputFrame {
+            held: ActionSet::from_iter(actions.iter().copied()),
+            ..Default::default()
+        };
+        if actions.contains(&Action::MoveRight) {
+            frame.set_axis(Axis::MoveX, 1.);
+        }
+        frame
+    }
+
+    /// Runs right, jumping every so often.
+    fn scripted_input(tick: u64) -> InputFrame {
+        let mut held = ActionSet::from_iter([Action::MoveRight]);
+        if tick % 50 < 20 {
+            held.insert(Action::Jump);
+        }
+
+        let mut frame = InputFrame {
+            held,
+            ..Default::default()
+        };
+        frame.set_axis(Axis::MoveX, 1.);
+        frame
+    }
+
+    async fn run(seed: u64, ticks: u64) -> World {
+        let level = resources::load_level(LEVEL).await.unwrap();
+        let mut world = World::new(level, seed);
+
+        let dt = FixedTimestep::default().dt();
+        for tick in 0..ticks {
+            world.step(scripted_input(tick), dt);
+        }
+        world
+    }
+
+    #[tokio::test]
+    async fn same_seed_and_input_give_the_same_world() {
+        let a = run(7, 600).await;
+        let b = run(7, 600).await;
+
+        assert_eq!(a.ticks, 600);
+        // The input has to actually move the player for this to mean much
+        assert_ne!(a.instance.position, run(7, 0).await.instance.position);
+        assert_eq!(a.checksum(), b.checksum());
+    }
+
+    #[test]
+    fn different_seeds_give_different_runs() {
+        const ROOM: &str = "
+..........
+..........
+..........
+..........
+##########";
+        let spawns = [Vec2::new(24., 64.), Vec2::new(104., 64.)];
+        let trajectory = |seed| {
+            let mut world = World::new(level(ROOM, &spawns), seed);
+            (0..60)
+                .map(|_| {
+                    world.step(holding(&[Action::MoveRight]), DT);
+                    world.instance.position
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = trajectory(0);
+        assert_eq!(first, trajectory(0));
+        assert!(
+            (1..8).any(|seed| trajectory(seed) != first),
+            "every seed spawned the player in the same place"
+        );
+    }
+
+    #[tokio::test]
+    async fn headless_runs_are_deterministic() {
+        let a = crate::run_headless(300, 1).await.unwrap();
+        let b = crate::run_headless(300, 1).await.unwrap();
+        assert_eq!(a.checksum(), b.checksum());
+    }
+
+    const SPIKES: &str = "
+..........
+..........
+..........
+....^.....
+##########";
+
+    #[test]
+    fn walking_into_a_hazard_respawns_the_player() {
+        let mut world = World::new(level(SPIKES, &[Vec2::new(8., 64.)]), 0);
+        let spawn = world.instance.position;
+
+        let mut hurt_at = None;
+        for tick in 0..60 {
+            world.step(holding(&[Action::MoveRight]), DT);
+            if world.events.contains(&WorldEvent::Hurt) {
+                hurt_at = Some(tick);
+                break;
+            }
+        }
+
+        // 32 pixels to the spikes, at up to 120 pixels a second
+        let tick = hurt_at.expect("never got hurt");
+        assert!(
+            tick >= 16,
+            "hurt on tick {} before reaching the spikes",
+            tick
+        );
+        assert_eq!(world.instance.position, spawn);
+        assert_eq!(world.body.velocity, Vec2::ZERO);
+    }
+
+    #[test]
+    fn standing_beside_a_hazard_is_safe() {
+        // Feet on the floor, right next to the spikes
+        let mut world = World::new(level(SPIKES, &[Vec2::new(48., 64.)]), 0);
+
+        for _ in 0..60 {
+            world.step(InputFrame::default(), DT);
+            assert!(world.events.is_empty(), "{:?}", world.events);
+        }
+        assert!(world.body.grounded);
+    }
+
+    /// A 20x10 room with a floor along the bottom, its top at y = 144.
+    const ROOM: &str = "
+....................
+....................
+....................
+....................
+....................
+....................
+....................
+....................
+....................
+####################";
+
+    /// The same room with a block on the floor, from x = 128 to 144.
+    const ROOM_WITH_BLOCK: &str = "
+....................
+....................
+....................
+....................
+....................
+....................
+....................
+....................
+........#...........
+####################";
+
+    /// Adds a `moving_platform` following `points` to `level`.
+    fn add_platform(level: &mut Level, points: &[Vec2], properties: &[(&str, PropertyValue)]) {
+        level.objects.push(LevelObject {
+            id: level.objects.len() as u32 + 1,
+            name: "platform".into(),
+            class: "moving_platform".into(),
+            layer: "objects".into(),
+            position: points[0],
+            size: Vec2::ZERO,
+            polyline: Some(points.to_vec()),
+            properties: properties
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        });
+    }
+
+    /// A world with the player standing out of the way and a platform
+    /// moving 32 pixels every half second along `points`.
+    fn platform_world(points: &[Vec2], properties: &[(&str, PropertyValue)]) -> World {
+        let mut level = level(ROOM, &[Vec2::new(8., 144.)]);
+        let mut all = vec![("speed", PropertyValue::Float(64.))];
+        all.extend(properties.iter().cloned());
+        add_platform(&mut level, points, &all);
+        World::new(level, 0)
+    }
+
+    /// Where the platform is after each of `steps` eighths of a second.
+    fn platform_positions(world: &mut World, steps: usize) -> Vec<Vec2> {
+        (0..steps)
+            .map(|_| {
+                world.step(InputFrame::default(), 0.125);
+                world.platforms[0].position
+            })
+            .collect()
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.abs_diff_eq(b, 1e-3), "{} != {}", a, b);
+    }
+
+    const A: Vec2 = Vec2::new(160., 32.);
+    const B: Vec2 = Vec2::new(192., 32.);
+    const C: Vec2 = Vec2::new(192., 64.);
+    const D: Vec2 = Vec2::new(160., 64.);
+
+    #[test]
+    fn looping_platforms_go_round() {
+        let mut world = platform_world(
+            &[A, B, C, D],
+            &[("mode", PropertyValue::String("loop".into()))],
+        );
+        let positions = platform_positions(&mut world, 20);
+
+        // Every fourth step is a waypoint, back to the start after the last
+        for (position, waypoint) in positions.iter().skip(3).step_by(4).zip([B, C, D, A, B]) {
+            assert_near(*position, waypoint);
+        }
+        assert_near(positions[1], A.lerp(B, 0.5));
+        assert_near(positions[13], D.lerp(A, 0.5));
+    }
+
+    #[test]
+    fn ping_pong_platforms_turn_around() {
+        let mut world = platform_world(
+            &[A, B, C],
+            &[("mode", PropertyValue::String("ping_pong".into()))],
+        );
+        let positions = platform_positions(&mut world, 20);
+
+        for (position, waypoint) in positions.iter().skip(3).step_by(4).zip([B, C, B, A, B]) {
+            assert_near(*position, waypoint);
+        }
+        assert_near(positions[9], C.lerp(B, 0.5));
+    }
+
+    #[test]
+    fn platforms_wait_at_each_waypoint() {
+        let mut world = platform_world(&[A, B], &[("wait", PropertyValue::Float(0.25))]);
+        let positions = platform_positions(&mut world, 12);
+
+        // Waiting at the start, moving, waiting at the end, then coming back
+        let expected = [
+            A,
+            A,
+            A.lerp(B, 0.25),
+            A.lerp(B, 0.5),
+            A.lerp(B, 0.75),
+            B,
+            B,
+            B,
+            B.lerp(A, 0.25),
+        ];
+        for (position, expected) in positions.iter().zip(expected) {
+            assert_near(*position, expected);
+        }
+    }
+
+    #[test]
+    fn eased_platforms_speed_up_and_slow_down() {
+        let mut world = platform_world(
+            &[A, B],
+            &[("easing", PropertyValue::String("quad_in_out".into()))],
+        );
+        let positions = platform_positions(&mut world, 4);
+
+        assert_near(positions[0], A + Vec2::new(4., 0.));
+        assert_near(positions[1], A + Vec2::new(16., 0.));
+        assert_near(positions[2], A + Vec2::new(28., 0.));
+        assert_near(positions[3], B);
+    }
+
+    /// A world in `layout` with the player's feet at `spawn` and a solid
+    /// platform moving at 60 pixels a second from `from` to `to`.
+    fn riding_world(layout: &str, spawn: Vec2, from: Vec2, to: Vec2) -> World {
+        let mut level = level(layout, &[spawn]);
+        add_platform(
+            &mut level,
+            &[from, to],
+            &[("speed", PropertyValue::Float(60.))],
+        );
+        World::new(level, 0)
+    }
+
+    #[test]
+    fn platforms_carry_riders_sideways() {
+        let mut world = riding_world(
+            ROOM,
+            Vec2::new(80., 112.),
+            Vec2::new(64., 112.),
+            Vec2::new(256., 112.),
+        );
+
+        let offset = |world: &World| world.instance.position - world.platforms[0].position;
+        for _ in 0..10 {
+            world.step(InputFrame::default(), DT);
+        }
+        let start = offset(&world);
+
+        for _ in 0..60 {
+            world.step(InputFrame::default(), DT);
+            assert!(world.body.grounded);
+            assert_near(offset(&world), start);
+        }
+        assert!(world.platforms[0].position.x > 120.);
+    }
+
+    #[test]
+    fn platforms_carry_riders_down() {
+        let mut world = riding_world(
+            ROOM,
+            Vec2::new(80., 48.),
+            Vec2::new(64., 48.),
+            Vec2::new(64., 112.),
+        );
+
+        for _ in 0..10 {
+            world.step(InputFrame::default(), DT);
+        }
+        for _ in 0..50 {
+            world.step(InputFrame::default(), DT);
+            let feet = world.body.bounds(world.instance.position).max.y;
+            assert!(world.body.grounded);
+            assert!(
+                (feet - world.platforms[0].bounds().min.y).abs() < 0.5,
+                "feet at {}",
+                feet
+            );
+        }
+    }
+
+    #[test]
+    fn platforms_push_the_player_out_of_the_way() {
+        // The player stands on the floor in the platform's path
+        let mut world = riding_world(
+            ROOM,
+            Vec2::new(104., 144.),
+            Vec2::new(32., 128.),
+            Vec2::new(256., 128.),
+        );
+
+        for _ in 0..90 {
+            world.step(InputFrame::default(), DT);
+            assert!(world.events.is_empty(), "{:?}", world.events);
+
+            let player = world.body.bounds(world.instance.position);
+            let platform = world.platforms[0].bounds();
+            assert!(
+                player.min.x >= platform.max.x - 0.1,
+                "{:?} inside {:?}",
+                player,
+                platform
+            );
+        }
+        assert!(world.instance.position.x > 104.);
+    }
+
+    #[test]
+    fn pushing_the_player_into_a_wall_crushes_them() {
+        let spawn = Vec2::new(104., 144.);
+        let mut world = riding_world(
+            ROOM_WITH_BLOCK,
+            spawn,
+            Vec2::new(32., 128.),
+            Vec2::new(256., 128.),
+        );
+        let start = world.instance.position;
+
+        let crushed = (0..90).any(|_| {
+            world.step(InputFrame::default(), DT);
+            world.events.contains(&WorldEvent::Crushed)
+        });
+        assert!(crushed, "never got crushed");
+        assert_eq!(world.instance.position, start);
+    }
+
+    #[test]
+    fn coming_down_on_the_player_crushes_them() {
+        let mut world = riding_world(
+            ROOM,
+            Vec2::new(104., 144.),
+            Vec2::new(96., 32.),
+            Vec2::new(96., 160.),
+        );
+
+        let crushed_at = (0..120).position(|_| {
+            world.step(InputFrame::default(), DT);
+            world.events.contains(&WorldEvent::Crushed)
+        });
+        // The platform's bottom reaches the player's head after 80 pixels
+        let tick = crushed_at.expect("never got crushed");
+        assert!(
+            tick >= 79,
+            "crushed on tick {} before reaching the player",
+            tick
+        );
+    }
+}